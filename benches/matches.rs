@@ -0,0 +1,141 @@
+//! Measures `State::matches` over a large scrollback built from the sample files, next to a naive search of
+//! every pattern on every line, like before the patterns were prefiltered with a `RegexSet`.
+//!
+//! Run it with `cargo bench`.
+
+#![allow(dead_code, unused_imports)]
+
+#[macro_use]
+extern crate lazy_static;
+
+#[path = "../src/alphabets.rs"]
+mod alphabets;
+#[path = "../src/ansi.rs"]
+mod ansi;
+#[path = "../src/error.rs"]
+mod error;
+#[path = "../src/history.rs"]
+mod history;
+#[path = "../src/state.rs"]
+mod state;
+
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs;
+use std::time::Instant;
+
+const LINES: usize = 50_000;
+const ITERATIONS: u32 = 5;
+
+fn main() {
+  let mut samples = fs::read_dir("samples")
+    .expect("Unable to read samples")
+    .map(|entry| entry.unwrap().path())
+    .collect::<Vec<_>>();
+
+  samples.sort();
+
+  let sample = samples
+    .iter()
+    .map(|path| fs::read_to_string(path).unwrap())
+    .collect::<Vec<_>>()
+    .join("\n");
+
+  let lines = sample.split('\n').cycle().take(LINES).collect::<Vec<&str>>();
+
+  measure("samples", &lines);
+
+  // Long lines full of matches, like minified logs
+  let dense = (0..LINES / 1000)
+    .map(|index| format!("{:x} 127.0.0.1 /var/log/{} ", 0x1000000 + index, index).repeat(200))
+    .collect::<Vec<_>>();
+  let lines = dense.iter().map(|line| line.as_str()).collect::<Vec<&str>>();
+
+  measure("dense", &lines);
+}
+
+fn measure(name: &str, lines: &[&str]) {
+  let alphabets = HashMap::new();
+  // Counting the matches keeps them from being optimized away
+  let mut found = 0;
+  let start = Instant::now();
+
+  for _ in 0..ITERATIONS {
+    let lines = lines.iter().map(|line| ansi::Line::parse(line)).collect::<Vec<_>>();
+    let state = state::State::new(
+      &lines,
+      alphabets::get_alphabet("qwerty", &alphabets).unwrap(),
+      state::Options::default(),
+    )
+    .unwrap();
+
+    found += state.matches(false, false, false).len();
+  }
+
+  let elapsed = start.elapsed() / ITERATIONS;
+
+  let mut naive_found = 0;
+  let start = Instant::now();
+
+  for _ in 0..ITERATIONS {
+    naive_found += naive(lines).len();
+  }
+
+  let naive_elapsed = start.elapsed() / ITERATIONS;
+
+  println!(
+    "{}: {} lines, {} matches in {:?} per iteration, {} naive ones in {:?} ({:.1}x)",
+    name,
+    lines.len(),
+    found / ITERATIONS as usize,
+    elapsed,
+    naive_found / ITERATIONS as usize,
+    naive_elapsed,
+    naive_elapsed.as_secs_f64() / elapsed.as_secs_f64()
+  );
+}
+
+/// The matching before the patterns were precompiled and prefiltered: every pattern is compiled on each
+/// call, then searched on the rest of every line, until none matches. The leftmost match wins.
+fn naive<'a>(lines: &[&'a str]) -> Vec<&'a str> {
+  let exclude = [("bash", r"[[:cntrl:]]\[([0-9]{1,2};)?([0-9]{1,2})?m")];
+  let patterns = exclude
+    .iter()
+    .copied()
+    .chain(state::PATTERNS.iter().map(|tuple| (tuple.0, tuple.2)))
+    .map(|(name, regexp)| (name, Regex::new(regexp).unwrap()))
+    .collect::<Vec<_>>();
+
+  let mut matches = Vec::new();
+
+  for line in lines {
+    let mut chunk: &str = line;
+
+    loop {
+      // Each clone starts with an empty cache, as it did
+      let submatches = patterns
+        .iter()
+        .filter_map(|(name, regex)| regex.find_iter(chunk).next().map(|m| (*name, regex.clone(), m)))
+        .collect::<Vec<_>>();
+
+      let (name, regex, matching) = match submatches.iter().min_by_key(|submatch| submatch.2.start()) {
+        Some(first) => first,
+        None => break,
+      };
+
+      let captures = regex.captures(matching.as_str()).unwrap();
+
+      if *name != "bash" {
+        match captures.name("match") {
+          Some(capture) => matches.push(capture.as_str()),
+          None if captures.len() > 1 => matches.extend(captures.iter().skip(1).flatten().map(|c| c.as_str())),
+          None => matches.push(matching.as_str()),
+        }
+      }
+
+      chunk = &chunk[matching.end()..];
+    }
+  }
+
+  matches
+}
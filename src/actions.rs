@@ -0,0 +1,70 @@
+/// A way to pick a hint other than typing it: holding a modifier key while typing its last letter, or
+/// pressing a leader key before it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Modifier {
+  Shift,
+  Alt,
+  Ctrl,
+  Leader(char),
+}
+
+/// A named action, given in the `%A` output to the hints picked with its modifier
+#[derive(Debug, Clone, PartialEq)]
+pub struct Action<'a> {
+  pub modifier: Modifier,
+  pub name: &'a str,
+}
+
+/// Parses an action as `MODIFIER=NAME`, like `alt=open`. The `leader` modifier needs a leader key.
+pub fn parse<'a>(definition: &'a str, leader: Option<char>) -> Result<Action<'a>, String> {
+  let (modifier, name) = definition
+    .split_once('=')
+    .ok_or_else(|| format!("Invalid action {}, use MODIFIER=NAME", definition))?;
+
+  let modifier = match (modifier, leader) {
+    ("shift", _) => Modifier::Shift,
+    ("alt", _) => Modifier::Alt,
+    ("ctrl", _) => Modifier::Ctrl,
+    ("leader", Some(key)) => Modifier::Leader(key),
+    ("leader", None) => return Err(format!("Action {} needs a leader key", definition)),
+    _ => return Err(format!("Unknown modifier: {}", modifier)),
+  };
+
+  if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+    return Err(format!("Invalid action name: {:?}", name));
+  }
+
+  Ok(Action { modifier, name })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse_actions() {
+    assert_eq!(
+      parse("alt=open", None),
+      Ok(Action {
+        modifier: Modifier::Alt,
+        name: "open"
+      })
+    );
+    assert_eq!(
+      parse("leader=edit", Some(',')),
+      Ok(Action {
+        modifier: Modifier::Leader(','),
+        name: "edit"
+      })
+    );
+  }
+
+  #[test]
+  fn invalid_actions() {
+    assert!(parse("alt", None).is_err());
+    assert!(parse("meta=open", None).is_err());
+    assert!(parse("leader=edit", None).is_err());
+    assert!(parse("ctrl=", None).is_err());
+    assert!(parse("ctrl=open:tab", None).is_err());
+  }
+}
@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 
-const ALPHABETS: [(&'static str, &'static str); 22] = [
+const ALPHABETS: [(&str, &str); 22] = [
   ("numeric", "1234567890"),
   ("abcd", "abcd"),
   ("qwerty", "asdfqwerzxcvjklmiuopghtybn"),
@@ -25,58 +25,249 @@ const ALPHABETS: [(&'static str, &'static str); 22] = [
   ("colemak-right-hand", "neioluymjhk"),
 ];
 
+// Keys of each keyboard layout, by row: numbers, top, home and bottom. Alphabets take the geometry of
+// the layout their name starts with.
+const LAYOUTS: [(&str, [&str; 4]); 8] = [
+  ("numeric", ["1234567890", "qwertyuiop", "asdfghjkl;", "zxcvbnm,./"]),
+  ("qwerty", ["1234567890", "qwertyuiop", "asdfghjkl;", "zxcvbnm,./"]),
+  ("azerty", ["1234567890", "azertyuiop", "qsdfghjklm", "wxcvbn,;:!"]),
+  ("qwertz", ["1234567890", "qwertzuiop", "asdfghjklö", "yxcvbnm,.-"]),
+  ("dvorak", ["1234567890", "',.pyfgcrl", "aoeuidhtns", ";qjkxbmwvz"]),
+  ("colemak", ["1234567890", "qwfpgjluy;", "arstdhneio", "zxcvbkm,./"]),
+  ("workman", ["1234567890", "qdrwbjfup;", "ashtgyneoi", "zxmcvkl,./"]),
+  ("norman", ["1234567890", "qwdfkjurl;", "asetgynioh", "zxcvbpm,./"]),
+];
+
+const HOME_ROW: usize = 2;
+
+// Finger of each column of keys, from the left pinky (0) to the right pinky (7)
+const FINGERS: [usize; 10] = [0, 1, 2, 3, 3, 4, 4, 5, 6, 7];
+
 pub struct Alphabet<'a> {
   letters: &'a str,
+  layout: Option<&'static [&'static str; 4]>,
 }
 
 impl<'a> Alphabet<'a> {
-  fn new(letters: &'a str) -> Alphabet {
-    Alphabet { letters }
+  pub fn new(letters: &'a str) -> Alphabet<'a> {
+    Alphabet { letters, layout: None }
+  }
+
+  /// An alphabet whose hints are comfortable to type on this keyboard layout, if known
+  pub fn with_layout(letters: &'a str, layout_name: &str) -> Alphabet<'a> {
+    let layout = LAYOUTS
+      .iter()
+      .find(|(name, _)| layout_name.starts_with(name))
+      .map(|(_, rows)| rows);
+
+    Alphabet {
+      layout,
+      ..Alphabet::new(letters)
+    }
   }
 
+  /// Row and column of a letter on the keyboard
+  fn key(&self, letter: char) -> Option<(usize, usize)> {
+    self
+      .layout?
+      .iter()
+      .enumerate()
+      .find_map(|(row, keys)| keys.chars().position(|key| key == letter).map(|column| (row, column)))
+  }
+
+  /// Effort to type a letter after another one. Keys out of the home row cost more, and so do
+  /// repeated keys, same hand sequences and, above all, different keys with the same finger.
+  fn effort(&self, previous: char, letter: char) -> usize {
+    let (previous_key, key) = match (self.key(previous), self.key(letter)) {
+      (Some(previous_key), Some(key)) => (previous_key, key),
+      _ => return 0,
+    };
+
+    let reach = key.0.abs_diff(HOME_ROW);
+    let (previous_finger, finger) = (FINGERS[previous_key.1], FINGERS[key.1]);
+
+    if previous_key == key {
+      reach + 1
+    } else if previous_finger == finger {
+      reach + 4 + previous_key.0.abs_diff(key.0)
+    } else if (previous_finger < 4) == (finger < 4) {
+      reach + 1
+    } else {
+      reach
+    }
+  }
+
+  /// Letters to follow a prefix, from the most comfortable to type. Ties keep the alphabet order.
+  fn next_letters<'l>(&self, prefix: &str, letters: &'l [String]) -> Vec<&'l String> {
+    let mut next = letters.iter().collect::<Vec<_>>();
+
+    if let Some(previous) = prefix.chars().last() {
+      next.sort_by_key(|letter| self.effort(previous, letter.chars().next().unwrap()));
+    }
+
+    next
+  }
+
+  /// Every hint of one more letter than these prefixes
+  fn expand(&self, prefixes: &[String], letters: &[String]) -> Vec<String> {
+    prefixes
+      .iter()
+      .flat_map(|prefix| {
+        self
+          .next_letters(prefix, letters)
+          .into_iter()
+          .map(move |letter| prefix.clone() + letter)
+      })
+      .collect()
+  }
+
+  /// Prefix-free hints for this number of matches, with the least keystrokes in total. All of them have
+  /// the same length, or one more letter, and the shortest ones come first.
   pub fn hints(&self, matches: usize) -> Vec<String> {
     let letters: Vec<String> = self.letters.chars().map(|s| s.to_string()).collect();
 
-    let mut expansion = letters.clone();
-    let mut expanded: Vec<String> = Vec::new();
+    if matches <= letters.len() {
+      return letters.into_iter().take(matches).collect();
+    }
 
-    loop {
-      if expansion.len() + expanded.len() >= matches {
-        break;
-      }
-      if expansion.is_empty() {
-        break;
-      }
+    // Every prefix of the longest hints but one, in order. Their last ones get expanded with a new
+    // letter, while the first ones are short enough to be hints on their own.
+    let mut prefixes = letters.clone();
+
+    while prefixes.len() * letters.len() < matches {
+      prefixes = self.expand(&prefixes, &letters);
+    }
+
+    // Each expanded prefix adds as many hints as letters, but one (itself)
+    let expanded = (matches - prefixes.len() + letters.len() - 2) / (letters.len() - 1);
+    let short = prefixes.len() - expanded;
+
+    // The first expanded prefix only needs the remaining hints
+    let remaining = matches - short - (expanded - 1) * letters.len();
+
+    let mut hints = prefixes[..short].to_vec();
+
+    for (index, prefix) in prefixes[short..].iter().enumerate() {
+      let needed = if index == 0 { remaining } else { letters.len() };
 
-      let prefix = expansion.pop().expect("Ouch!");
-      let sub_expansion: Vec<String> = letters
+      hints.extend(
+        self
+          .next_letters(prefix, &letters)
+          .into_iter()
+          .take(needed)
+          .map(|letter| prefix.clone() + letter),
+      );
+    }
+
+    hints
+  }
+
+  /// Hints for this number of matches, all of them with the same length: the shortest one for all of them
+  pub fn fixed_hints(&self, matches: usize) -> Vec<String> {
+    let letters: Vec<String> = self.letters.chars().map(|s| s.to_string()).collect();
+    let mut hints = letters.clone();
+
+    while hints.len() < matches {
+      hints = self.expand(&hints, &letters);
+    }
+
+    hints.truncate(matches);
+    hints
+  }
+
+  /// Uppercase letters can't be typed along with the shift modifier
+  pub fn has_uppercase(&self) -> bool {
+    self.letters.chars().any(|letter| letter.is_uppercase())
+  }
+
+  /// Tells if the hint is typed with letters of this alphabet
+  pub fn spells(&self, hint: &str) -> bool {
+    !hint.is_empty() && hint.chars().all(|letter| self.letters.contains(letter))
+  }
+
+  /// Hints for this number of matches besides the taken ones, so that no hint is a prefix of another.
+  /// There are none when the taken hints leave no room for them.
+  pub fn hints_besides(&self, matches: usize, taken: &[&str], fixed_length: bool) -> Option<Vec<String>> {
+    let free = |hint: &String| {
+      !taken
         .iter()
-        .take(matches - expansion.len() - expanded.len())
-        .map(|s| prefix.clone() + s)
-        .collect();
+        .any(|taken| hint.starts_with(taken) || taken.starts_with(hint.as_str()))
+    };
+    let total = matches + taken.len();
 
-      expanded.splice(0..0, sub_expansion);
+    if fixed_length {
+      let length = self.fixed_hints(total).first().map_or(0, |hint| hint.chars().count());
+
+      if taken.iter().any(|taken| taken.chars().count() != length) {
+        return None;
+      }
+
+      let every = self.fixed_hints(self.letters.chars().count().pow(length as u32));
+
+      return Some(every.into_iter().filter(free).take(matches).collect());
+    }
+
+    // Longer hints leave more room around the taken ones
+    let mut size = total;
+
+    while size <= total * self.letters.chars().count() {
+      let mut hints = self.hints(size).into_iter().filter(free).collect::<Vec<_>>();
+
+      if hints.len() >= matches {
+        hints.truncate(matches);
+        return Some(hints);
+      }
+
+      size *= 2;
     }
 
-    expansion = expansion.iter().take(matches - expanded.len()).cloned().collect();
-    expansion.append(&mut expanded);
-    expansion
+    None
   }
 }
 
-pub fn get_alphabet(alphabet_name: &str) -> Alphabet {
-  let alphabets: HashMap<&str, &str> = ALPHABETS.iter().cloned().collect();
+/// Finds an alphabet by name, among the built-in ones and the `custom` ones of a patterns file. A
+/// `chars:` prefix gives the letters of the alphabet instead, like `chars:fjdksla`.
+pub fn get_alphabet<'a>(alphabet_name: &'a str, custom: &'a HashMap<String, String>) -> Result<Alphabet<'a>, String> {
+  let letters = if let Some(letters) = alphabet_name.strip_prefix("chars:") {
+    letters
+  } else if let Some(letters) = custom.get(alphabet_name) {
+    letters.as_str()
+  } else if let Some((_, letters)) = ALPHABETS.iter().find(|(name, _)| *name == alphabet_name) {
+    letters
+  } else {
+    return Err(format!("Unknown alphabet: {}", alphabet_name));
+  };
 
-  alphabets
-    .get(alphabet_name)
-    .expect(format!("Unknown alphabet: {}", alphabet_name).as_str()); // FIXME
+  if letters.chars().count() < 2 {
+    return Err(format!("Alphabet {} needs at least two letters", alphabet_name));
+  }
+
+  if let Some(letter) = letters
+    .chars()
+    .find(|letter| letter.is_whitespace() || letter.is_control())
+  {
+    return Err(format!("Alphabet {} can't use the letter {:?}", alphabet_name, letter));
+  }
 
-  Alphabet::new(alphabets[alphabet_name])
+  if let Some((index, letter)) = letters
+    .char_indices()
+    .find(|(index, letter)| letters[..*index].contains(*letter))
+  {
+    return Err(format!(
+      "Alphabet {} repeats the letter {:?} at position {}",
+      alphabet_name,
+      letter,
+      letters[..index].chars().count() + 1
+    ));
+  }
+
+  Ok(Alphabet::with_layout(letters, alphabet_name))
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
+  use proptest::prelude::*;
 
   #[test]
   fn simple_matches() {
@@ -103,6 +294,171 @@ mod tests {
   fn composed_matches_max() {
     let alphabet = Alphabet::new("ab");
     let hints = alphabet.hints(8);
+    assert_eq!(hints, ["aaa", "aab", "aba", "abb", "baa", "bab", "bba", "bbb"]);
+  }
+
+  #[test]
+  fn composed_matches_full() {
+    let alphabet = Alphabet::new("ab");
+    let hints = alphabet.hints(4);
     assert_eq!(hints, ["aa", "ab", "ba", "bb"]);
   }
+
+  #[test]
+  fn composed_matches_deep() {
+    let alphabet = Alphabet::new("ab");
+    let hints = alphabet.hints(6);
+    assert_eq!(hints, ["aa", "ab", "baa", "bab", "bba", "bbb"]);
+  }
+
+  #[test]
+  fn fixed_matches() {
+    let alphabet = Alphabet::new("abcd");
+    assert_eq!(alphabet.fixed_hints(3), ["a", "b", "c"]);
+    assert_eq!(alphabet.fixed_hints(6), ["aa", "ab", "ac", "ad", "ba", "bb"]);
+  }
+
+  #[test]
+  fn comfortable_matches() {
+    // On qwerty, e, d and c share the same finger, while k is on the other hand
+    let alphabet = Alphabet::with_layout("dkce", "qwerty");
+    assert_eq!(alphabet.hints(6), ["d", "k", "c", "ek", "ee", "ed"]);
+    assert_eq!(alphabet.fixed_hints(4), ["d", "k", "c", "e"]);
+    assert_eq!(alphabet.fixed_hints(5)[..4], ["dk", "dd", "dc", "de"]);
+  }
+
+  #[test]
+  fn effort() {
+    let alphabet = Alphabet::with_layout("asdfjkl", "qwerty-homerow");
+
+    assert_eq!(alphabet.effort('f', 'j'), 0);
+    assert_eq!(alphabet.effort('f', 'd'), 1);
+    assert_eq!(alphabet.effort('f', 'f'), 1);
+    assert_eq!(alphabet.effort('f', 'g'), 4);
+    assert_eq!(alphabet.effort('f', 'r'), 6);
+    assert_eq!(Alphabet::new("asdf").effort('f', 'g'), 0);
+  }
+
+  #[test]
+  fn hints_besides_taken() {
+    let alphabet = Alphabet::new("abcd");
+
+    assert_eq!(
+      alphabet.hints_besides(3, &["b"], false),
+      Some(vec!["a".to_string(), "c".to_string(), "d".to_string()])
+    );
+    assert_eq!(
+      alphabet.hints_besides(4, &["b", "c"], false),
+      Some(vec![
+        "a".to_string(),
+        "da".to_string(),
+        "db".to_string(),
+        "dc".to_string()
+      ])
+    );
+    assert_eq!(alphabet.hints_besides(1, &["a", "b", "c", "d"], false), None);
+  }
+
+  #[test]
+  fn fixed_hints_besides_taken() {
+    let alphabet = Alphabet::new("ab");
+
+    assert_eq!(
+      alphabet.hints_besides(2, &["ab"], true),
+      Some(vec!["aa".to_string(), "ba".to_string()])
+    );
+    assert_eq!(alphabet.hints_besides(2, &["a"], true), None);
+  }
+
+  #[test]
+  fn no_matches() {
+    let alphabet = Alphabet::new("abcd");
+    assert!(alphabet.hints(0).is_empty());
+  }
+
+  #[test]
+  fn named_alphabet() {
+    let custom = HashMap::new();
+    let alphabet = get_alphabet("qwerty-homerow", &custom).unwrap();
+    assert_eq!(alphabet.letters, "asdfjklgh");
+  }
+
+  #[test]
+  fn literal_alphabet() {
+    let custom = HashMap::new();
+    let alphabet = get_alphabet("chars:fjdksla", &custom).unwrap();
+    assert_eq!(alphabet.letters, "fjdksla");
+    assert!(!alphabet.has_uppercase());
+  }
+
+  #[test]
+  fn uppercase_alphabet() {
+    let custom = HashMap::new();
+    let alphabet = get_alphabet("chars:fjFJ", &custom).unwrap();
+    assert_eq!(alphabet.letters, "fjFJ");
+    assert!(alphabet.has_uppercase());
+  }
+
+  #[test]
+  fn custom_alphabet() {
+    let custom = [("workman".to_string(), "ashtneoi".to_string())]
+      .iter()
+      .cloned()
+      .collect();
+    let alphabet = get_alphabet("workman", &custom).unwrap();
+    assert_eq!(alphabet.letters, "ashtneoi");
+  }
+
+  #[test]
+  fn unknown_alphabet() {
+    assert!(get_alphabet("norman", &HashMap::new()).is_err());
+  }
+
+  #[test]
+  fn invalid_alphabets() {
+    let custom = HashMap::new();
+    assert_eq!(
+      get_alphabet("chars:fjdkf", &custom).err().unwrap(),
+      "Alphabet chars:fjdkf repeats the letter 'f' at position 5"
+    );
+    assert!(get_alphabet("chars:f", &custom).is_err());
+    assert!(get_alphabet("chars:fj k", &custom).is_err());
+  }
+
+  proptest! {
+    #[test]
+    fn hints_are_prefix_free(size in 2..27usize, matches in 0..3000usize) {
+      let letters = &"abcdefghijklmnopqrstuvwxyz"[..size];
+      let mut hints = Alphabet::new(letters).hints(matches);
+
+      prop_assert_eq!(hints.len(), matches);
+
+      // In lexicographic order, a hint is followed by the hints it prefixes
+      hints.sort();
+
+      for pair in hints.windows(2) {
+        prop_assert!(!pair[1].starts_with(&pair[0]), "{} prefixes {}", pair[0], pair[1]);
+      }
+    }
+
+    #[test]
+    fn hints_are_the_shortest(size in 2..27usize, matches in 1..3000usize) {
+      let letters = &"abcdefghijklmnopqrstuvwxyz"[..size];
+      let hints = Alphabet::new(letters).hints(matches);
+      let lengths = hints.iter().map(|hint| hint.len()).collect::<Vec<_>>();
+
+      // The longest hints are as short as possible
+      let longest = *lengths.last().unwrap();
+      prop_assert!(size.pow(longest as u32) >= matches);
+      prop_assert!(longest == 1 || size.pow(longest as u32 - 1) < matches);
+
+      // The shortest ones come first, and they are as many as possible
+      prop_assert!(lengths.windows(2).all(|pair| pair[0] <= pair[1]));
+      prop_assert!(lengths[0] + 1 >= longest);
+
+      let short = lengths.iter().filter(|length| **length < longest).count();
+      let prefixes = size.pow(longest as u32 - 1);
+      prop_assert!(longest == 1 || short + 1 > prefixes || short + 1 + (prefixes - short - 1) * size < matches);
+    }
+  }
 }
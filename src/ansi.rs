@@ -0,0 +1,284 @@
+use std::borrow::Cow;
+use unicode_width::UnicodeWidthStr;
+
+const ESC: char = '\x1b';
+const BEL: char = '\x07';
+const CSI: char = '\u{9b}';
+const OSC: char = '\u{9d}';
+const ST: char = '\u{9c}';
+
+/// A captured line, split into its visible text and the escape sequences within it. The offsets of
+/// the escape sequences map the raw line to the text, and `column` maps the text to the screen.
+pub struct Line<'a> {
+  /// The visible text, without escape sequences
+  pub text: Cow<'a, str>,
+  /// Escape sequences, with the offset in `text` where they appear
+  pub escapes: Vec<(usize, &'a str)>,
+}
+
+impl<'a> Line<'a> {
+  pub fn parse(raw: &'a str) -> Line<'a> {
+    // Most lines have no escape sequences at all
+    if !raw.contains(|c: char| c.is_control() && c != '\t') {
+      return Line {
+        text: Cow::Borrowed(raw),
+        escapes: Vec::new(),
+      };
+    }
+
+    let mut text = String::with_capacity(raw.len());
+    let mut escapes = Vec::new();
+    let mut position = 0;
+
+    while position < raw.len() {
+      let length = escape_length(&raw[position..]);
+
+      if length > 0 {
+        escapes.push((text.len(), &raw[position..position + length]));
+        position += length;
+      } else {
+        let c = raw[position..].chars().next().unwrap();
+        text.push(c);
+        position += c.len_utf8();
+      }
+    }
+
+    Line {
+      text: Cow::Owned(text),
+      escapes,
+    }
+  }
+
+  /// Maps an offset in `text` to the screen column where it is displayed
+  pub fn column(&self, offset: usize) -> usize {
+    self.text[..offset].width_cjk()
+  }
+
+  /// The visible text along with its SGR escape sequences only, to replay its colors and attributes
+  pub fn styled(&self) -> String {
+    let mut styled = String::with_capacity(self.text.len());
+    let mut position = 0;
+
+    for (at, escape) in self.escapes.iter().filter(|(_, escape)| is_sgr(escape)) {
+      styled.push_str(&self.text[position..*at]);
+      styled.push_str(escape);
+      position = *at;
+    }
+
+    styled.push_str(&self.text[position..]);
+    styled
+  }
+
+  /// OSC 8 hyperlinks of the line, as the `start..end` range of their text and their URI
+  pub fn links(&self) -> Vec<(usize, usize, &'a str)> {
+    let mut links = Vec::new();
+    let mut open: Option<(usize, &str)> = None;
+
+    for (at, escape) in self.escapes.iter() {
+      if let Some(uri) = hyperlink(escape) {
+        if let Some((start, uri)) = open.take() {
+          if start < *at {
+            links.push((start, *at, uri));
+          }
+        }
+
+        // An empty URI closes the hyperlink
+        if !uri.is_empty() {
+          open = Some((*at, uri));
+        }
+      }
+    }
+
+    if let Some((start, uri)) = open {
+      if start < self.text.len() {
+        links.push((start, self.text.len(), uri));
+      }
+    }
+
+    links
+  }
+}
+
+/// Tells if the escape sequence sets colors or attributes, as `CSI params m`
+fn is_sgr(escape: &str) -> bool {
+  (escape.starts_with("\x1b[") || escape.starts_with(CSI)) && escape.ends_with('m')
+}
+
+/// URI of an OSC 8 hyperlink escape sequence, formatted as `OSC 8 ; params ; URI ST`
+fn hyperlink(escape: &str) -> Option<&str> {
+  let body = escape
+    .strip_prefix("\x1b]8;")
+    .or_else(|| escape.strip_prefix("\u{9d}8;"))?;
+  let body = body
+    .strip_suffix("\x1b\\")
+    .or_else(|| body.strip_suffix(BEL))
+    .or_else(|| body.strip_suffix(ST))?;
+
+  body.split_once(';').map(|(_, uri)| uri)
+}
+
+/// Length of the escape sequence or control character starting the text, or 0 for visible text
+fn escape_length(text: &str) -> usize {
+  let mut chars = text.char_indices();
+
+  let first = match chars.next() {
+    Some((_, c)) if c.is_control() && c != '\t' => c,
+    _ => return 0,
+  };
+
+  match first {
+    ESC => match chars.next() {
+      Some((index, '[')) => index + 1 + csi_length(&text[index + 1..]),
+      Some((index, ']')) | Some((index, 'P')) | Some((index, 'X')) | Some((index, '^')) | Some((index, '_')) => {
+        index + 1 + string_length(&text[index + 1..])
+      }
+      // Intermediate bytes, then a final byte, like `ESC ( B`
+      Some((index, c)) if ('\x20'..='\x2f').contains(&c) => {
+        let rest = &text[index..];
+        let end = rest
+          .char_indices()
+          .find(|(_, c)| !('\x20'..='\x2f').contains(c))
+          .map_or(rest.len(), |(end, c)| end + c.len_utf8());
+
+        index + end
+      }
+      Some((index, c)) => index + c.len_utf8(),
+      None => first.len_utf8(),
+    },
+    CSI => first.len_utf8() + csi_length(&text[first.len_utf8()..]),
+    OSC => first.len_utf8() + string_length(&text[first.len_utf8()..]),
+    c => c.len_utf8(),
+  }
+}
+
+/// Length of the parameters, the intermediate and the final bytes of a control sequence
+fn csi_length(text: &str) -> usize {
+  text
+    .char_indices()
+    .find(|(_, c)| ('\x40'..='\x7e').contains(c) || !('\x20'..='\x3f').contains(c))
+    .map_or(text.len(), |(index, c)| {
+      if ('\x40'..='\x7e').contains(&c) {
+        index + 1
+      } else {
+        // A broken sequence ends before the unexpected character
+        index
+      }
+    })
+}
+
+/// Length of a control string, up to its terminator (`BEL` or `ST`) included
+fn string_length(text: &str) -> usize {
+  let mut chars = text.char_indices().peekable();
+
+  while let Some((index, c)) = chars.next() {
+    match c {
+      BEL | ST => return index + c.len_utf8(),
+      ESC if matches!(chars.peek(), Some((_, '\\'))) => return index + 2,
+      _ => {}
+    }
+  }
+
+  text.len()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn plain_text() {
+    let line = Line::parse("lorem ipsum");
+
+    assert_eq!(line.text, "lorem ipsum");
+    assert!(line.escapes.is_empty());
+    assert_eq!(line.column(6), 6);
+  }
+
+  #[test]
+  fn sgr_sequences() {
+    let line = Line::parse("\x1b[38;5;208mfoo\x1b[0m \x1b[38;2;255;0;0mbar\x1b[m");
+
+    assert_eq!(line.text, "foo bar");
+    assert_eq!(
+      line.escapes,
+      [
+        (0, "\x1b[38;5;208m"),
+        (3, "\x1b[0m"),
+        (4, "\x1b[38;2;255;0;0m"),
+        (7, "\x1b[m")
+      ]
+    );
+  }
+
+  #[test]
+  fn cursor_sequences() {
+    let line = Line::parse("foo\x1b[K\x1b[2Abar\x1b(B\x1b7");
+
+    assert_eq!(line.text, "foobar");
+    assert_eq!(line.escapes.len(), 4);
+  }
+
+  #[test]
+  fn hyperlink_sequences() {
+    let line = Line::parse("\x1b]8;;https://foo.bar\x07link\x1b]8;;\x1b\\ \x1b]0;title\x1b\\");
+
+    assert_eq!(line.text, "link ");
+    assert_eq!(
+      line.escapes,
+      [
+        (0, "\x1b]8;;https://foo.bar\x07"),
+        (4, "\x1b]8;;\x1b\\"),
+        (5, "\x1b]0;title\x1b\\")
+      ]
+    );
+  }
+
+  #[test]
+  fn styled_text() {
+    let line = Line::parse("\x1b[1;31mfoo\x1b[K\x1b[0m \x1b]8;;https://bar.baz\x07bar\x1b]8;;\x07");
+
+    assert_eq!(line.styled(), "\x1b[1;31mfoo\x1b[0m bar");
+    assert_eq!(Line::parse("foo").styled(), "foo");
+  }
+
+  #[test]
+  fn hyperlinks() {
+    let line = Line::parse(
+      "ls \x1b]8;;file:///tmp/foo\x1b\\foo\x1b]8;;\x1b\\ \x1b]8;id=1;https://bar.baz\x07bar\x1b]8;;https://qux\x07qux",
+    );
+
+    assert_eq!(line.text, "ls foo barqux");
+    assert_eq!(
+      line.links(),
+      [
+        (3, 6, "file:///tmp/foo"),
+        (7, 10, "https://bar.baz"),
+        (10, 13, "https://qux")
+      ]
+    );
+  }
+
+  #[test]
+  fn broken_sequences() {
+    let line = Line::parse("foo\x1b[12\x1b]8;;bar");
+
+    assert_eq!(line.text, "foo");
+    assert_eq!(line.escapes.len(), 2);
+  }
+
+  #[test]
+  fn control_characters() {
+    let line = Line::parse("foo\r\x07\tbar");
+
+    assert_eq!(line.text, "foo\tbar");
+  }
+
+  #[test]
+  fn offsets() {
+    let line = Line::parse("\x1b[32m日本\x1b[m /tmp");
+
+    assert_eq!(line.text, "日本 /tmp");
+    assert_eq!(line.escapes, [(0, "\x1b[32m"), (6, "\x1b[m")]);
+    assert_eq!(line.column(7), 5);
+  }
+}
@@ -1,31 +1,163 @@
+use super::error::Error;
 use regex::Regex;
-use termion::color;
+use termion::{color, style};
 
-pub fn get_color(color_name: &str) -> Box<dyn color::Color> {
+/// A color along with text attributes, written like tmux does: `bold,colour208` or `#1b1cbf underscore`
+pub struct Style {
+  color: Box<dyn color::Color>,
+  attributes: String,
+}
+
+impl Style {
+  /// Escape sequences to use the style as the foreground
+  pub fn fg(&self) -> String {
+    format!("{}{}", self.attributes, color::Fg(&*self.color))
+  }
+
+  /// Escape sequences to use the style as the background
+  pub fn bg(&self) -> String {
+    format!("{}{}", self.attributes, color::Bg(&*self.color))
+  }
+}
+
+/// The default color of the terminal, without attributes
+impl Default for Style {
+  fn default() -> Style {
+    Style {
+      color: Box::new(color::Reset),
+      attributes: String::new(),
+    }
+  }
+}
+
+pub fn get_color(color_name: &str) -> Result<Box<dyn color::Color>, Error> {
   lazy_static! {
-    static ref RGB: Regex = Regex::new(r"#([[:xdigit:]]{2})([[:xdigit:]]{2})([[:xdigit:]]{2})").unwrap();
+    static ref RGB: Regex = Regex::new(r"^#([[:xdigit:]]{2})([[:xdigit:]]{2})([[:xdigit:]]{2})$").unwrap();
+    static ref SHORT_RGB: Regex = Regex::new(r"^#([[:xdigit:]])([[:xdigit:]])([[:xdigit:]])$").unwrap();
+    static ref INDEXED: Regex = Regex::new(r"^colou?r([0-9]{1,3})$").unwrap();
   }
 
-  if let Some(captures) = RGB.captures(color_name) {
-    let r = u8::from_str_radix(captures.get(1).unwrap().as_str(), 16).unwrap();
-    let g = u8::from_str_radix(captures.get(2).unwrap().as_str(), 16).unwrap();
-    let b = u8::from_str_radix(captures.get(3).unwrap().as_str(), 16).unwrap();
+  if let Some(captures) = RGB.captures(color_name).or_else(|| SHORT_RGB.captures(color_name)) {
+    // A single digit is repeated, so #abc is #aabbcc
+    let component = |index: usize| {
+      let digits = captures.get(index).unwrap().as_str();
+      let digits = if digits.len() == 1 {
+        digits.repeat(2)
+      } else {
+        digits.to_string()
+      };
+
+      u8::from_str_radix(&digits, 16).unwrap()
+    };
+
+    return Ok(Box::new(color::Rgb(component(1), component(2), component(3))));
+  }
 
-    return Box::new(color::Rgb(r, g, b));
+  if let Some(captures) = INDEXED.captures(color_name) {
+    if let Ok(index) = captures.get(1).unwrap().as_str().parse::<u8>() {
+      return Ok(Box::new(color::AnsiValue(index)));
+    }
   }
 
   match color_name {
-    "black" => Box::new(color::Black),
-    "red" => Box::new(color::Red),
-    "green" => Box::new(color::Green),
-    "yellow" => Box::new(color::Yellow),
-    "blue" => Box::new(color::Blue),
-    "magenta" => Box::new(color::Magenta),
-    "cyan" => Box::new(color::Cyan),
-    "white" => Box::new(color::White),
-    "default" => Box::new(color::Reset),
-    _ => panic!("Unknown color: {}", color_name),
+    "black" => Ok(Box::new(color::Black)),
+    "red" => Ok(Box::new(color::Red)),
+    "green" => Ok(Box::new(color::Green)),
+    "yellow" => Ok(Box::new(color::Yellow)),
+    "blue" => Ok(Box::new(color::Blue)),
+    "magenta" => Ok(Box::new(color::Magenta)),
+    "cyan" => Ok(Box::new(color::Cyan)),
+    "white" => Ok(Box::new(color::White)),
+    "brightblack" => Ok(Box::new(color::LightBlack)),
+    "brightred" => Ok(Box::new(color::LightRed)),
+    "brightgreen" => Ok(Box::new(color::LightGreen)),
+    "brightyellow" => Ok(Box::new(color::LightYellow)),
+    "brightblue" => Ok(Box::new(color::LightBlue)),
+    "brightmagenta" => Ok(Box::new(color::LightMagenta)),
+    "brightcyan" => Ok(Box::new(color::LightCyan)),
+    "brightwhite" => Ok(Box::new(color::LightWhite)),
+    "default" => Ok(Box::new(color::Reset)),
+    _ => Err(Error::UnknownColor(color_name.to_string())),
+  }
+}
+
+/// Escape sequence of a text attribute, with the names tmux gives them
+fn get_attribute(attribute_name: &str) -> Option<String> {
+  let attribute = match attribute_name {
+    "bold" | "bright" => style::Bold.to_string(),
+    "dim" => style::Faint.to_string(),
+    "italics" | "italic" => style::Italic.to_string(),
+    "underscore" | "underline" => style::Underline.to_string(),
+    "blink" => style::Blink.to_string(),
+    "reverse" => style::Invert.to_string(),
+    "strikethrough" => style::CrossedOut.to_string(),
+    _ => return None,
+  };
+
+  Some(attribute)
+}
+
+/// Parses a style: at most one color, the default one if missing, and any attributes
+pub fn get_style(style_name: &str) -> Result<Style, Error> {
+  let mut color = None;
+  let mut attributes = String::new();
+
+  for part in style_name
+    .split(|c: char| c == ',' || c.is_whitespace())
+    .filter(|part| !part.is_empty())
+  {
+    if let Some(attribute) = get_attribute(part) {
+      attributes.push_str(&attribute);
+    } else if color.is_none() {
+      color = Some(get_color(part)?);
+    } else {
+      return Err(Error::UnknownColor(style_name.to_string()));
+    }
+  }
+
+  Ok(Style {
+    color: color.unwrap_or_else(|| Box::new(color::Reset)),
+    attributes,
+  })
+}
+
+/// Parses a tmux style, like `fg=colour0,bg=#ffaf00,bold`, as its foreground and background styles. Its
+/// attributes go with the foreground, and anything else, like alignment, is ignored. Colors unknown to
+/// thumbs, like the X11 names of tmux, fall back to the default one.
+pub fn get_tmux_style(tmux_style: &str) -> (Style, Style) {
+  // The colors of the terminal are the default ones
+  fn known(color: &str) -> &str {
+    match color {
+      "terminal" => "default",
+      _ if get_color(color).is_err() => "default",
+      _ => color,
+    }
   }
+
+  let mut foreground = "default";
+  let mut background = "default";
+  let mut attributes = Vec::new();
+
+  for part in tmux_style
+    .split(|c: char| c == ',' || c.is_whitespace())
+    .filter(|part| !part.is_empty())
+  {
+    if let Some(color) = part.strip_prefix("fg=") {
+      foreground = known(color);
+    } else if let Some(color) = part.strip_prefix("bg=") {
+      background = known(color);
+    } else if get_attribute(part).is_some() {
+      attributes.push(part);
+    }
+  }
+
+  attributes.push(foreground);
+
+  // Every part is known by now
+  (
+    get_style(&attributes.join(",")).unwrap(),
+    get_style(background).unwrap(),
+  )
 }
 
 #[cfg(test)]
@@ -34,29 +166,107 @@ mod tests {
 
   #[test]
   fn match_color() {
-    let text1 = println!("{}{}", color::Fg(&*get_color("green")), "foo");
-    let text2 = println!("{}{}", color::Fg(color::Green), "foo");
+    let text1 = format!("{}foo", color::Fg(&*get_color("green").unwrap()));
+    let text2 = format!("{}foo", color::Fg(color::Green));
 
     assert_eq!(text1, text2);
   }
 
   #[test]
   fn parse_rgb() {
-    let text1 = println!("{}{}", color::Fg(&*get_color("#1b1cbf")), "foo");
-    let text2 = println!("{}{}", color::Fg(color::Rgb(27, 28, 191)), "foo");
+    let text1 = format!("{}foo", color::Fg(&*get_color("#1b1cbf").unwrap()));
+    let text2 = format!("{}foo", color::Fg(color::Rgb(27, 28, 191)));
+
+    assert_eq!(text1, text2);
+  }
+
+  #[test]
+  fn parse_short_rgb() {
+    let text1 = format!("{}foo", color::Fg(&*get_color("#1bf").unwrap()));
+    let text2 = format!("{}foo", color::Fg(color::Rgb(17, 187, 255)));
+
+    assert_eq!(text1, text2);
+  }
+
+  #[test]
+  fn parse_indexed() {
+    let text1 = format!("{}foo", color::Fg(&*get_color("colour208").unwrap()));
+    let text2 = format!("{}foo", color::Fg(color::AnsiValue(208)));
+
+    assert_eq!(text1, text2);
+    assert!(get_color("color0").is_ok());
+    assert!(get_color("colour256").is_err());
+  }
+
+  #[test]
+  fn parse_bright() {
+    let text1 = format!("{}foo", color::Fg(&*get_color("brightred").unwrap()));
+    let text2 = format!("{}foo", color::Fg(color::LightRed));
 
     assert_eq!(text1, text2);
   }
 
   #[test]
-  #[should_panic]
   fn parse_invalid_rgb() {
-    println!("{}{}", color::Fg(&*get_color("#1b1cbj")), "foo");
+    assert!(get_color("#1b1cbj").is_err());
+    assert!(get_color("#1b1cbff").is_err());
   }
 
   #[test]
-  #[should_panic]
   fn no_match_color() {
-    println!("{}{}", color::Fg(&*get_color("wat")), "foo");
+    assert!(get_color("wat").is_err());
+  }
+
+  #[test]
+  fn parse_style() {
+    let style = get_style("bold,colour208 underscore").unwrap();
+
+    assert_eq!(
+      style.fg(),
+      format!(
+        "{}{}{}",
+        style::Bold,
+        style::Underline,
+        color::Fg(color::AnsiValue(208))
+      )
+    );
+    assert_eq!(
+      get_style("reverse").unwrap().bg(),
+      format!("{}{}", style::Invert, color::Bg(color::Reset))
+    );
+  }
+
+  #[test]
+  fn parse_tmux_style() {
+    let (foreground, background) = get_tmux_style("fg=colour0,bg=#ffaf00 bold,align=centre");
+
+    assert_eq!(
+      foreground.fg(),
+      format!("{}{}", style::Bold, color::Fg(color::AnsiValue(0)))
+    );
+    assert_eq!(background.bg(), format!("{}", color::Bg(color::Rgb(255, 175, 0))));
+
+    let (foreground, background) = get_tmux_style("bg=terminal");
+
+    assert_eq!(foreground.fg(), format!("{}", color::Fg(color::Reset)));
+    assert_eq!(background.bg(), format!("{}", color::Bg(color::Reset)));
+  }
+
+  #[test]
+  fn unknown_tmux_colors() {
+    let (foreground, background) = get_tmux_style("fg=wat,bg=colour4");
+
+    assert_eq!(foreground.fg(), format!("{}", color::Fg(color::Reset)));
+    assert_eq!(background.bg(), format!("{}", color::Bg(color::AnsiValue(4))));
+
+    let (foreground, _) = get_tmux_style("fg=darkgoldenrod,bold");
+
+    assert_eq!(foreground.fg(), format!("{}{}", style::Bold, color::Fg(color::Reset)));
+  }
+
+  #[test]
+  fn invalid_styles() {
+    assert!(get_style("bold,wat").is_err());
+    assert!(get_style("red blue").is_err());
   }
 }
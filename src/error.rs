@@ -0,0 +1,86 @@
+use std::fmt;
+use std::io;
+
+/// Everything that stops thumbs before picking a hint. Invalid arguments are all reported before the
+/// terminal enters raw mode.
+#[derive(Debug)]
+pub enum Error {
+  /// The command line doesn't parse, or asks for the help or the version
+  Usage(clap::Error),
+  /// An argument with an invalid value, like a width that isn't a number
+  Argument(String),
+  UnknownColor(String),
+  Alphabet(String),
+  Action(String),
+  /// A custom pattern, from `--regexp` or the patterns file, whose regexp doesn't compile
+  Regexp {
+    name: String,
+    source: regex::Error,
+  },
+  PatternsFile(String),
+  Input(io::Error),
+  Terminal(io::Error),
+  Target {
+    path: String,
+    source: io::Error,
+  },
+  HintsFile {
+    path: String,
+    source: io::Error,
+  },
+}
+
+impl Error {
+  /// Exit code of the error. Exiting with 1 means that no hint was picked.
+  pub fn code(&self) -> i32 {
+    match self {
+      Error::Usage(_) | Error::Argument(_) | Error::UnknownColor(_) | Error::Alphabet(_) | Error::Action(_) => 2,
+      Error::Regexp { .. } | Error::PatternsFile(_) => 3,
+      Error::Input(_) | Error::Terminal(_) | Error::Target { .. } | Error::HintsFile { .. } => 4,
+    }
+  }
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Error::Usage(err) => write!(f, "{}", err.message),
+      Error::Argument(message) | Error::Alphabet(message) | Error::Action(message) | Error::PatternsFile(message) => {
+        write!(f, "{}", message)
+      }
+      Error::UnknownColor(name) => write!(f, "Unknown color: {}", name),
+      Error::Regexp { name, source } => write!(f, "Invalid regexp {}: {}", name, source),
+      Error::Input(source) => write!(f, "Unable to read the input: {}", source),
+      Error::Terminal(source) => write!(f, "Unable to set up the terminal: {}", source),
+      Error::Target { path, source } => write!(f, "Unable to write the target file {}: {}", path, source),
+      Error::HintsFile { path, source } => write!(f, "Unable to write the hints file {}: {}", path, source),
+    }
+  }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn exit_codes() {
+    assert_eq!(Error::UnknownColor("wat".to_string()).code(), 2);
+    assert_eq!(Error::PatternsFile("Invalid patterns file".to_string()).code(), 3);
+    assert_eq!(Error::Input(io::Error::from(io::ErrorKind::InvalidData)).code(), 4);
+  }
+
+  #[test]
+  fn messages() {
+    let err = Error::Target {
+      path: "/foo/bar".to_string(),
+      source: io::Error::from(io::ErrorKind::NotFound),
+    };
+
+    assert_eq!(
+      err.to_string(),
+      "Unable to write the target file /foo/bar: entity not found"
+    );
+  }
+}
@@ -0,0 +1,87 @@
+use std::fs;
+use std::io;
+
+/// A hint given to a match in a previous run, saved as a `hint TAB line TAB text` line of the hints file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+  pub hint: String,
+  /// Hash of the line where the match was found, to tell apart matches with the same text
+  pub line: u64,
+  pub text: String,
+}
+
+/// FNV-1a hash of a line. It is saved in the hints file, so it can't change between Rust releases like
+/// the one of `DefaultHasher` may.
+pub fn line_hash(line: &str) -> u64 {
+  line.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+    (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+  })
+}
+
+pub fn parse(content: &str) -> Vec<Entry> {
+  content
+    .lines()
+    .filter_map(|line| {
+      let mut fields = line.splitn(3, '\t');
+      let hint = fields.next()?;
+      let line = fields.next()?.parse::<u64>().ok()?;
+      let text = fields.next()?;
+
+      Some(Entry {
+        hint: hint.to_string(),
+        line,
+        text: text.to_string(),
+      })
+    })
+    .collect()
+}
+
+/// Hints of the previous run. A missing or broken file only means there is nothing to remember.
+pub fn load(path: &str) -> Vec<Entry> {
+  fs::read_to_string(path)
+    .map(|content| parse(&content))
+    .unwrap_or_default()
+}
+
+pub fn save(path: &str, entries: &[Entry]) -> io::Result<()> {
+  let content = entries
+    .iter()
+    .map(|entry| format!("{}\t{}\t{}\n", entry.hint, entry.line, entry.text))
+    .collect::<String>();
+
+  fs::write(path, content)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse_entries() {
+    let entries = parse("ab\t42\thttps://foo.bar\nbroken\nc\tnan\tfoo\nd\t7\tfoo\tbar\n");
+
+    assert_eq!(
+      entries,
+      [
+        Entry {
+          hint: "ab".to_string(),
+          line: 42,
+          text: "https://foo.bar".to_string()
+        },
+        Entry {
+          hint: "d".to_string(),
+          line: 7,
+          text: "foo\tbar".to_string()
+        }
+      ]
+    );
+  }
+
+  #[test]
+  fn line_hashes() {
+    assert_eq!(line_hash("foo bar"), line_hash("foo bar"));
+    assert_ne!(line_hash("foo bar"), line_hash("foo baz"));
+    assert_eq!(line_hash(""), 0xcbf2_9ce4_8422_2325);
+    assert_eq!(line_hash("a"), 0xaf63_dc4c_8601_ec8c);
+  }
+}
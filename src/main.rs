@@ -4,15 +4,23 @@ extern crate base64;
 extern crate clap;
 extern crate termion;
 
+mod actions;
 mod alphabets;
+mod ansi;
 mod colors;
+mod error;
+mod history;
+mod patterns;
 mod state;
 mod view;
 
 use self::clap::{App, Arg};
 use clap::crate_version;
+use error::Error;
+use std::collections::HashMap;
 use std::fs::OpenOptions;
 use std::io::{self, Read, Write};
+use std::process::Command;
 
 #[allow(dead_code)]
 fn dbg(msg: &str) {
@@ -24,24 +32,44 @@ fn dbg(msg: &str) {
   writeln!(&mut file, "{}", msg).expect("Unable to write log file");
 }
 
-fn app_args<'a>() -> clap::ArgMatches<'a> {
+fn app_args<'a>() -> Result<clap::ArgMatches<'a>, clap::Error> {
   App::new("thumbs")
     .version(crate_version!())
     .about("A lightning fast version copy/pasting like vimium/vimperator")
     .arg(
       Arg::with_name("alphabet")
-        .help("Sets the alphabet")
+        .help("Sets the alphabet, by name or as chars:LETTERS")
         .long("alphabet")
         .short("a")
         .default_value("qwerty"),
     )
     .arg(
       Arg::with_name("format")
-        .help("Specifies the out format for the picked hint. (%U: Upcase, %H: Hint)")
+        .help("Specifies the out format for the picked hint. (%A: Action, %U: Upcase, %P: Pattern, %H: Hint)")
         .long("format")
         .short("f")
         .default_value("%H"),
     )
+    .arg(
+      Arg::with_name("action")
+        .help("Pick hints with a modifier (shift, alt, ctrl or leader) for this action, as MODIFIER=NAME")
+        .long("action")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1),
+    )
+    .arg(
+      Arg::with_name("leader_key")
+        .help("Key to press before a hint to pick it for the leader action")
+        .long("leader-key")
+        .takes_value(true),
+    )
+    .arg(
+      Arg::with_name("navigation_key")
+        .help("Key to press to move the selection with h, j, k, l, w, b, gg and G")
+        .long("navigation-key")
+        .takes_value(true),
+    )
     .arg(
       Arg::with_name("foreground_color")
         .help("Sets the foregroud color for matches")
@@ -90,6 +118,27 @@ fn app_args<'a>() -> clap::ArgMatches<'a> {
         .long("select-bg-color")
         .default_value("black"),
     )
+    .arg(
+      Arg::with_name("pattern_foreground_color")
+        .help("Sets the foreground color for the matches of a pattern, as name=COLOR")
+        .long("pattern-fg-color")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1),
+    )
+    .arg(
+      Arg::with_name("pattern_background_color")
+        .help("Sets the background color for the matches of a pattern, as name=COLOR")
+        .long("pattern-bg-color")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1),
+    )
+    .arg(
+      Arg::with_name("style_from_tmux")
+        .help("Take the styles from the tmux theme, unless set one by one")
+        .long("style-from-tmux"),
+    )
     .arg(
       Arg::with_name("multi")
         .help("Enable multi-selection")
@@ -108,27 +157,86 @@ fn app_args<'a>() -> clap::ArgMatches<'a> {
         .long("unique")
         .short("u"),
     )
+    .arg(
+      Arg::with_name("fixed_length")
+        .help("Use hints of the same length for all matches")
+        .long("fixed-length"),
+    )
     .arg(
       Arg::with_name("position")
         .help("Hint position")
         .long("position")
         .default_value("left")
+        .possible_values(&["left", "right", "off_left", "off_right"])
         .short("p"),
     )
     .arg(
       Arg::with_name("regexp")
-        .help("Use this regexp as extra pattern to match")
+        .help("Use this regexp as extra pattern to match. Name it with name:NAME=PATTERN")
         .long("regexp")
         .short("x")
         .takes_value(true)
         .multiple(true),
     )
+    .arg(
+      Arg::with_name("patterns_file")
+        .help("Load extra patterns from this TOML file")
+        .long("patterns-file")
+        .takes_value(true),
+    )
+    .arg(
+      Arg::with_name("disable_pattern")
+        .help("Disable this pattern by name")
+        .long("disable-pattern")
+        .takes_value(true)
+        .multiple(true)
+        .use_delimiter(true),
+    )
+    .arg(
+      Arg::with_name("only_pattern")
+        .help("Only match this pattern by name")
+        .long("only-pattern")
+        .takes_value(true)
+        .multiple(true)
+        .use_delimiter(true),
+    )
+    .arg(
+      Arg::with_name("wrap_pattern")
+        .help("Let matches of this pattern continue on the next line when a line fills the pane width")
+        .long("wrap-pattern")
+        .takes_value(true)
+        .multiple(true)
+        .use_delimiter(true),
+    )
+    .arg(
+      Arg::with_name("width")
+        .help("Pane width used to find wrapped lines. Defaults to the terminal width")
+        .long("width")
+        .takes_value(true),
+    )
     .arg(
       Arg::with_name("contrast")
         .help("Put square brackets around hint for visibility")
         .long("contrast")
         .short("c"),
     )
+    .arg(
+      Arg::with_name("status")
+        .help("Show the status bar, also toggled with ?")
+        .long("status"),
+    )
+    .arg(
+      Arg::with_name("keep_colors")
+        .help("Keep the colors of the input under the hints")
+        .long("keep-colors")
+        .short("k"),
+    )
+    .arg(
+      Arg::with_name("hints_file")
+        .help("Give the same hints to the same matches as the last run with this file")
+        .long("hints-file")
+        .takes_value(true),
+    )
     .arg(
       Arg::with_name("target")
         .help("Stores the hint in the specified path")
@@ -136,93 +244,358 @@ fn app_args<'a>() -> clap::ArgMatches<'a> {
         .short("t")
         .takes_value(true),
     )
-    .get_matches()
+    .get_matches_safe()
+}
+
+/// Value of a global tmux option, if tmux runs and knows about it
+fn tmux_option(name: &str) -> Option<String> {
+  let output = Command::new("tmux").args(["show", "-gv", name]).output().ok()?;
+
+  if !output.status.success() {
+    return None;
+  }
+
+  Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Value of an argument taking a single key, like the leader key
+fn single_key(args: &clap::ArgMatches, name: &str, label: &str) -> Result<Option<char>, Error> {
+  let key = match args.value_of(name) {
+    Some(key) => key,
+    None => return Ok(None),
+  };
+  let mut chars = key.chars();
+
+  match (chars.next(), chars.next()) {
+    (Some(key), None) => Ok(Some(key)),
+    _ => Err(Error::Argument(format!("The {} must be a single key: {}", label, key))),
+  }
 }
 
 fn main() {
-  let args = app_args();
+  match run() {
+    Ok(true) => {}
+    Ok(false) => ::std::process::exit(1),
+    Err(err) => {
+      match err {
+        Error::Usage(ref usage) if !usage.use_stderr() => usage.exit(),
+        Error::Usage(ref usage) => eprintln!("{}", usage.message),
+        ref err => eprintln!("error: {}", err),
+      }
+
+      ::std::process::exit(err.code());
+    }
+  }
+}
+
+/// Picks hints and prints them, or writes them to the target file. Every argument is checked before
+/// the terminal enters raw mode. Tells if any hint was picked.
+fn run() -> Result<bool, Error> {
+  let args = app_args().map_err(Error::Usage)?;
   let format = args.value_of("format").unwrap();
-  let alphabet = args.value_of("alphabet").unwrap();
+  let alphabet_name = args.value_of("alphabet").unwrap();
   let position = args.value_of("position").unwrap();
   let target = args.value_of("target");
+  let hints_file = args.value_of("hints_file");
   let multi = args.is_present("multi");
   let reverse = args.is_present("reverse");
   let unique = args.is_present("unique");
+  let fixed_length = args.is_present("fixed_length");
   let contrast = args.is_present("contrast");
+  let keep_colors = args.is_present("keep_colors");
+  let show_status = args.is_present("status");
   let regexp = if let Some(items) = args.values_of("regexp") {
     items.collect::<Vec<_>>()
   } else {
     [].to_vec()
   };
+  let file = if let Some(path) = args.value_of("patterns_file") {
+    patterns::load(path).map_err(Error::PatternsFile)?
+  } else {
+    patterns::File::default()
+  };
+  let definitions = file.pattern;
+  let alphabet = alphabets::get_alphabet(alphabet_name, &file.alphabets).map_err(Error::Alphabet)?;
+  let leader_key = single_key(&args, "leader_key", "leader key")?;
+  let navigation_key = single_key(&args, "navigation_key", "navigation key")?;
+  let mut actions = if let Some(items) = args.values_of("action") {
+    items
+      .map(|item| actions::parse(item, leader_key))
+      .collect::<Result<Vec<_>, _>>()
+      .map_err(Error::Action)?
+  } else {
+    [].to_vec()
+  };
+  let shifted = actions.iter().any(|action| action.modifier == actions::Modifier::Shift);
+
+  // Shift types the uppercase letters of an alphabet. Otherwise, it picks the upcase action by default.
+  if alphabet.has_uppercase() && shifted {
+    return Err(Error::Action(format!(
+      "Alphabet {} has uppercase letters, they can't be typed with shift",
+      alphabet_name
+    )));
+  } else if !alphabet.has_uppercase() && !shifted {
+    actions.push(actions::Action {
+      modifier: actions::Modifier::Shift,
+      name: "upcase",
+    });
+  }
 
-  let foreground_color = colors::get_color(args.value_of("foreground_color").unwrap());
-  let background_color = colors::get_color(args.value_of("background_color").unwrap());
-  let hint_foreground_color = colors::get_color(args.value_of("hint_foreground_color").unwrap());
-  let hint_background_color = colors::get_color(args.value_of("hint_background_color").unwrap());
-  let select_foreground_color = colors::get_color(args.value_of("select_foreground_color").unwrap());
-  let select_background_color = colors::get_color(args.value_of("select_background_color").unwrap());
-  let multi_foreground_color = colors::get_color(args.value_of("multi_foreground_color").unwrap());
-  let multi_background_color = colors::get_color(args.value_of("multi_background_color").unwrap());
+  // Terminals send Ctrl+i as Tab, and Ctrl+j or Ctrl+m as Enter
+  let ctrl = actions.iter().any(|action| action.modifier == actions::Modifier::Ctrl);
+
+  if let Some(letter) = ['i', 'j', 'm']
+    .iter()
+    .find(|letter| ctrl && alphabet.spells(&letter.to_string()))
+  {
+    return Err(Error::Action(format!(
+      "Alphabet {} has the letter {}, it can't be typed with ctrl",
+      alphabet_name, letter
+    )));
+  }
+
+  if let Some(key) = leader_key.filter(|key| alphabet.spells(&key.to_string())) {
+    return Err(Error::Action(format!(
+      "The leader key {:?} is a letter of the alphabet {}",
+      key, alphabet_name
+    )));
+  }
+
+  if let Some(key) = navigation_key.filter(|key| alphabet.spells(&key.to_string())) {
+    return Err(Error::Argument(format!(
+      "The navigation key {:?} is a letter of the alphabet {}",
+      key, alphabet_name
+    )));
+  }
+
+  if navigation_key.is_some() && navigation_key == leader_key {
+    return Err(Error::Argument(
+      "The navigation key and the leader key must be different".to_string(),
+    ));
+  }
+
+  let disabled = if let Some(items) = args.values_of("disable_pattern") {
+    items.collect::<Vec<_>>()
+  } else {
+    [].to_vec()
+  };
+  let only = if let Some(items) = args.values_of("only_pattern") {
+    items.collect::<Vec<_>>()
+  } else {
+    [].to_vec()
+  };
+  let wrapped = if let Some(items) = args.values_of("wrap_pattern") {
+    items.collect::<Vec<_>>()
+  } else {
+    [].to_vec()
+  };
+  let width = if let Some(width) = args.value_of("width") {
+    let width = width
+      .parse::<usize>()
+      .map_err(|_| Error::Argument(format!("Invalid width: {}", width)))?;
+
+    Some(width)
+  } else {
+    termion::terminal_size().ok().map(|(width, _)| width as usize)
+  };
+
+  // Matches look like the ones of a search in copy mode, hints like messages and the selection like
+  // the one of copy mode
+  let mut themed = HashMap::new();
+
+  if args.is_present("style_from_tmux") {
+    let tmux_styles = [
+      ("copy-mode-match-style", "foreground_color", "background_color"),
+      ("message-style", "hint_foreground_color", "hint_background_color"),
+      ("mode-style", "select_foreground_color", "select_background_color"),
+      (
+        "copy-mode-current-match-style",
+        "multi_foreground_color",
+        "multi_background_color",
+      ),
+    ];
+
+    for (option, foreground, background) in tmux_styles.iter() {
+      // Without tmux, the styles keep their defaults
+      if let Some(tmux_style) = tmux_option(option) {
+        let (foreground_style, background_style) = colors::get_tmux_style(&tmux_style);
+
+        themed.insert(*foreground, foreground_style);
+        themed.insert(*background, background_style);
+      }
+    }
+  }
+
+  let mut get_style = |name: &str| match themed.remove(name) {
+    Some(style) if args.occurrences_of(name) == 0 => Ok(style),
+    _ => colors::get_style(args.value_of(name).unwrap()),
+  };
+
+  let foreground_color = get_style("foreground_color")?;
+  let background_color = get_style("background_color")?;
+  let hint_foreground_color = get_style("hint_foreground_color")?;
+  let hint_background_color = get_style("hint_background_color")?;
+  let select_foreground_color = get_style("select_foreground_color")?;
+  let select_background_color = get_style("select_background_color")?;
+  let multi_foreground_color = get_style("multi_foreground_color")?;
+  let multi_background_color = get_style("multi_background_color")?;
+
+  // Patterns from the command line go first, then the ones from the patterns file
+  let custom = regexp
+    .iter()
+    .map(|regexp| state::Pattern::parse(regexp))
+    .chain(definitions.iter().map(|definition| state::Pattern {
+      name: &definition.name,
+      regexp: &definition.regexp,
+      priority: definition.priority.unwrap_or(state::DEFAULT_PRIORITY),
+      transform: definition.transform.as_deref(),
+    }))
+    .collect::<Vec<_>>();
+
+  let mut pattern_colors = HashMap::new();
+  let mut pattern_background_colors = HashMap::new();
+
+  for definition in definitions.iter() {
+    if let Some(color) = &definition.color {
+      pattern_colors.insert(definition.name.as_str(), colors::get_style(color)?);
+    }
+
+    if let Some(color) = &definition.bg_color {
+      pattern_background_colors.insert(definition.name.as_str(), colors::get_style(color)?);
+    }
+  }
+
+  // Colors from the command line override the ones of the patterns file
+  let pattern_styles = [
+    ("pattern_foreground_color", &mut pattern_colors),
+    ("pattern_background_color", &mut pattern_background_colors),
+  ];
+
+  for (arg, styles) in pattern_styles {
+    for item in args.values_of(arg).into_iter().flatten() {
+      let (name, color) = item
+        .split_once('=')
+        .ok_or_else(|| Error::Argument(format!("Invalid pattern color {}, use name=COLOR", item)))?;
+
+      if !state::known(&custom, name) {
+        return Err(Error::Argument(format!("Unknown pattern: {}", name)));
+      }
+
+      styles.insert(name, colors::get_style(color)?);
+    }
+  }
+
+  // The target file is opened up front, not to lose the picked hints on a bad path
+  let target_file = if let Some(path) = target {
+    let file = OpenOptions::new()
+      .create(true)
+      .truncate(true)
+      .write(true)
+      .open(path)
+      .map_err(|source| Error::Target {
+        path: path.to_string(),
+        source,
+      })?;
+
+    Some(file)
+  } else {
+    None
+  };
 
   let stdin = io::stdin();
   let mut handle = stdin.lock();
   let mut output = String::new();
 
-  handle.read_to_string(&mut output).unwrap();
+  handle.read_to_string(&mut output).map_err(Error::Input)?;
+
+  let lines = output.split('\n').map(ansi::Line::parse).collect::<Vec<_>>();
 
-  let lines = output.split('\n').collect::<Vec<&str>>();
+  let previous = if let Some(path) = hints_file {
+    history::load(path)
+  } else {
+    [].to_vec()
+  };
 
-  let mut state = state::State::new(&lines, alphabet, &regexp);
+  let mut state = state::State::new(
+    &lines,
+    alphabet,
+    state::Options {
+      custom: &custom,
+      disabled: &disabled,
+      only: &only,
+      wrapped: &wrapped,
+      width,
+      previous: &previous,
+    },
+  )?;
 
   let selected = {
     let mut viewbox = view::View::new(
       &mut state,
-      multi,
-      reverse,
-      unique,
-      contrast,
-      position,
-      select_foreground_color,
-      select_background_color,
-      multi_foreground_color,
-      multi_background_color,
-      foreground_color,
-      background_color,
-      hint_foreground_color,
-      hint_background_color,
+      view::Options {
+        multi,
+        reverse,
+        unique,
+        fixed_length,
+        contrast,
+        keep_colors,
+        show_status,
+        position,
+        select_foreground_color,
+        select_background_color,
+        multi_foreground_color,
+        multi_background_color,
+        foreground_color,
+        background_color,
+        hint_foreground_color,
+        hint_background_color,
+        pattern_colors,
+        pattern_background_colors,
+        actions: &actions,
+        navigation_key,
+      },
     );
 
-    viewbox.present()
-  };
+    let selected = viewbox.present()?;
 
-  if !selected.is_empty() {
-    let output = selected
-      .iter()
-      .map(|(text, upcase)| {
-        let upcase_value = if *upcase { "true" } else { "false" };
-
-        let mut output = format.to_string();
-
-        output = str::replace(&output, "%U", upcase_value);
-        output = str::replace(&output, "%H", text.as_str());
-        output
-      })
-      .collect::<Vec<_>>()
-      .join("\n");
-
-    if let Some(target) = target {
-      let mut file = OpenOptions::new()
-        .create(true)
-        .truncate(true)
-        .write(true)
-        .open(target)
-        .expect("Unable to open the target file");
-
-      file.write(output.as_bytes()).unwrap();
-    } else {
-      print!("{}", output);
+    if let Some(path) = hints_file {
+      history::save(path, &viewbox.remembered()).map_err(|source| Error::HintsFile {
+        path: path.to_string(),
+        source,
+      })?;
     }
+
+    selected
+  };
+
+  if selected.is_empty() {
+    return Ok(false);
+  }
+
+  let output = selected
+    .iter()
+    .map(|(text, pattern, action)| {
+      let upcase_value = if *action == Some("upcase") { "true" } else { "false" };
+
+      let mut output = format.to_string();
+
+      output = str::replace(&output, "%A", action.unwrap_or(""));
+      output = str::replace(&output, "%U", upcase_value);
+      output = str::replace(&output, "%P", pattern.as_str());
+      output = str::replace(&output, "%H", text.as_str());
+      output
+    })
+    .collect::<Vec<_>>()
+    .join("\n");
+
+  if let (Some(mut file), Some(path)) = (target_file, target) {
+    file.write_all(output.as_bytes()).map_err(|source| Error::Target {
+      path: path.to_string(),
+      source,
+    })?;
   } else {
-    ::std::process::exit(1);
+    print!("{}", output);
   }
+
+  Ok(true)
 }
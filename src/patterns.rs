@@ -0,0 +1,144 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+
+/// A pattern loaded from a patterns file:
+///
+/// ```toml
+/// [[pattern]]
+/// name = "jira"
+/// regexp = "(?P<match>JIRA-(?P<id>[0-9]+))"
+/// priority = 10
+/// action = "xdg-open {}"
+/// color = "magenta"
+/// bg_color = "black"
+/// transform = "https://jira.example.com/browse/JIRA-${id}"
+/// ```
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Definition {
+  pub name: String,
+  pub regexp: String,
+  pub priority: Option<i32>,
+  // Only read by tmux-thumbs, to run it after picking a match
+  #[allow(dead_code)]
+  pub action: Option<String>,
+  pub color: Option<String>,
+  pub bg_color: Option<String>,
+  pub transform: Option<String>,
+}
+
+/// A patterns file, with its patterns and its named alphabets:
+///
+/// ```toml
+/// [alphabets]
+/// workman = "ashtneoi"
+/// ```
+#[derive(Deserialize, Debug, Default)]
+pub struct File {
+  #[serde(default)]
+  pub pattern: Vec<Definition>,
+  #[serde(default)]
+  pub alphabets: HashMap<String, String>,
+}
+
+/// Parses a patterns file. Pattern names are made of letters, digits, `_` and `-`, as they go in the output
+/// of tmux-thumbs, split by `:`, and in the names of tmux options.
+pub fn parse(content: &str) -> Result<File, String> {
+  let file: File = toml::from_str(content).map_err(|err| err.to_string())?;
+
+  let valid = |name: &str| !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-');
+
+  if let Some(definition) = file.pattern.iter().find(|definition| !valid(&definition.name)) {
+    return Err(format!(
+      "Invalid pattern name {:?}, use letters, digits, _ or -",
+      definition.name
+    ));
+  }
+
+  Ok(file)
+}
+
+pub fn load(path: &str) -> Result<File, String> {
+  let content = fs::read_to_string(path).map_err(|err| format!("Unable to read patterns file {}: {}", path, err))?;
+
+  parse(&content).map_err(|err| format!("Invalid patterns file {}: {}", path, err))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse_definitions() {
+    let content = r##"
+      [[pattern]]
+      name = "jira"
+      regexp = "JIRA-[0-9]+"
+      priority = 10
+      action = "xdg-open {}"
+      color = "magenta"
+      bg_color = "colour236"
+
+      [[pattern]]
+      name = "ticket"
+      regexp = "#(?P<id>[0-9]+)"
+      transform = "https://tickets.example.com/${id}"
+    "##;
+
+    let definitions = parse(content).unwrap().pattern;
+
+    assert_eq!(definitions.len(), 2);
+    assert_eq!(definitions[0].name, "jira");
+    assert_eq!(definitions[0].priority, Some(10));
+    assert_eq!(definitions[0].action, Some("xdg-open {}".to_string()));
+    assert_eq!(definitions[0].color, Some("magenta".to_string()));
+    assert_eq!(definitions[0].bg_color, Some("colour236".to_string()));
+    assert_eq!(definitions[1].bg_color, None);
+    assert_eq!(definitions[1].priority, None);
+    assert_eq!(
+      definitions[1].transform,
+      Some("https://tickets.example.com/${id}".to_string())
+    );
+  }
+
+  #[test]
+  fn parse_empty() {
+    let file = parse("").unwrap();
+
+    assert_eq!(file.pattern, vec![]);
+    assert!(file.alphabets.is_empty());
+  }
+
+  #[test]
+  fn parse_alphabets() {
+    let content = r##"
+      [alphabets]
+      workman = "ashtneoi"
+      split = "fjdkslaghrueiwoqp"
+    "##;
+
+    let alphabets = parse(content).unwrap().alphabets;
+
+    assert_eq!(alphabets.len(), 2);
+    assert_eq!(alphabets["workman"], "ashtneoi");
+  }
+
+  #[test]
+  fn parse_unknown_field() {
+    assert!(parse("[[pattern]]\nname = \"a\"\nregexp = \"a\"\nfoo = 1").is_err());
+  }
+
+  #[test]
+  fn parse_missing_regexp() {
+    assert!(parse("[[pattern]]\nname = \"a\"").is_err());
+  }
+
+  #[test]
+  fn parse_invalid_names() {
+    assert!(parse("[[pattern]]\nname = \"a:b\"\nregexp = \"a\"").is_err());
+    assert!(parse("[[pattern]]\nname = \"a b\"\nregexp = \"a\"").is_err());
+    assert!(parse("[[pattern]]\nname = \"\"\nregexp = \"a\"").is_err());
+    assert!(parse("[[pattern]]\nname = \"jira-2_b\"\nregexp = \"a\"").is_ok());
+  }
+}
@@ -1,36 +1,64 @@
-use regex::Regex;
-use std::collections::HashMap;
+use super::alphabets::Alphabet;
+use super::ansi::Line;
+use super::error::Error;
+use super::history::{line_hash, Entry};
+use regex::{Regex, RegexSet};
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
+use unicode_width::UnicodeWidthStr;
 
-const EXCLUDE_PATTERNS: [(&'static str, &'static str); 1] = [("bash", r"[[:cntrl:]]\[([0-9]{1,2};)?([0-9]{1,2})?m")];
+// Priority of the user defined patterns, unless they set their own. Above all built-in patterns.
+pub const DEFAULT_PRIORITY: i32 = 100;
 
-const PATTERNS: [(&'static str, &'static str); 15] = [
-  ("markdown_url", r"\[[^]]*\]\(([^)]+)\)"),
-  ("url", r"(?P<match>(https?://|git@|git://|ssh://|ftp://|file:///)[^ ]+)"),
+// Priority of the OSC 8 hyperlinks. Above the built-in patterns, as they come from the program itself.
+const HYPERLINK_PRIORITY: i32 = 16;
+
+pub const PATTERNS: [(&str, i32, &str); 15] = [
+  ("markdown_url", 15, r"\[[^]]*\]\(([^)]+)\)"),
+  (
+    "url",
+    14,
+    r"(?P<match>(https?://|git@|git://|ssh://|ftp://|file:///)[^ ]+)",
+  ),
   (
     "diff_summary",
+    13,
     r"diff --git a/([.\w\-@~\[\]]+?/[.\w\-@\[\]]++) b/([.\w\-@~\[\]]+?/[.\w\-@\[\]]++)",
   ),
-  ("diff_a", r"--- a/([^ ]+)"),
-  ("diff_b", r"\+\+\+ b/([^ ]+)"),
-  ("docker", r"sha256:([0-9a-f]{64})"),
-  ("path", r"(?P<match>([.\w\-@$~\[\]]+)?(/[.\w\-@$\[\]]+)+)"),
-  ("color", r"#[0-9a-fA-F]{6}"),
-  ("uid", r"[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}"),
-  ("ipfs", r"Qm[0-9a-zA-Z]{44}"),
-  ("sha", r"[0-9a-f]{7,40}"),
-  ("ip", r"\d{1,3}\.\d{1,3}\.\d{1,3}\.\d{1,3}"),
-  ("ipv6", r"[A-f0-9:]+:+[A-f0-9:]+[%\w\d]+"),
-  ("address", r"0x[0-9a-fA-F]+"),
-  ("number", r"[0-9]{4,}"),
+  ("diff_a", 12, r"--- a/([^ ]+)"),
+  ("diff_b", 11, r"\+\+\+ b/([^ ]+)"),
+  ("docker", 10, r"sha256:([0-9a-f]{64})"),
+  ("path", 9, r"(?P<match>([.\w\-@$~\[\]]+)?(/[.\w\-@$\[\]]+)+)"),
+  ("color", 8, r"#[0-9a-fA-F]{6}"),
+  (
+    "uid",
+    7,
+    r"[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}",
+  ),
+  ("ipfs", 6, r"Qm[0-9a-zA-Z]{44}"),
+  ("address", 5, r"0x[0-9a-fA-F]+"),
+  ("sha", 4, r"[0-9a-f]{7,40}"),
+  ("ip", 3, r"\d{1,3}\.\d{1,3}\.\d{1,3}\.\d{1,3}"),
+  ("ipv6", 2, r"[A-f0-9:]+:+[A-f0-9:]+[%\w\d]+"),
+  ("number", 1, r"[0-9]{4,}"),
 ];
 
-#[derive(Clone)]
-pub struct Match<'a> {
+/// The piece of a match shown on a single line of the screen
+#[derive(Clone, Debug, PartialEq)]
+pub struct Span<'a> {
   pub x: i32,
   pub y: i32,
-  pub pattern: &'a str,
   pub text: &'a str,
+}
+
+#[derive(Clone)]
+pub struct Match<'a> {
+  pub spans: Vec<Span<'a>>,
+  pub pattern: &'a str,
+  pub text: Cow<'a, str>,
+  pub copy: String,
   pub hint: Option<String>,
 }
 
@@ -38,11 +66,11 @@ impl<'a> fmt::Debug for Match<'a> {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     write!(
       f,
-      "Match {{ x: {}, y: {}, pattern: {}, text: {}, hint: <{}> }}",
-      self.x,
-      self.y,
+      "Match {{ spans: {:?}, pattern: {}, text: {}, copy: {}, hint: <{}> }}",
+      self.spans,
       self.pattern,
       self.text,
+      self.copy,
       self.hint.clone().unwrap_or("<undefined>".to_string())
     )
   }
@@ -50,157 +78,692 @@ impl<'a> fmt::Debug for Match<'a> {
 
 impl<'a> PartialEq for Match<'a> {
   fn eq(&self, other: &Match) -> bool {
-    self.x == other.x && self.y == other.y
+    self.spans == other.spans
+  }
+}
+
+/// A user defined pattern, coming from `--regexp` or from a patterns file.
+#[derive(Clone)]
+pub struct Pattern<'a> {
+  pub name: &'a str,
+  pub regexp: &'a str,
+  pub priority: i32,
+  pub transform: Option<&'a str>,
+}
+
+impl<'a> Pattern<'a> {
+  /// Parses a custom regexp in the form `name:NAME=PATTERN`. Any other regexp, like `key=value`, is taken as is
+  /// and labeled as `custom`.
+  pub fn parse(regexp: &'a str) -> Pattern<'a> {
+    lazy_static! {
+      static ref NAMED: Regex = Regex::new(r"^name:([\w-]+)=(.+)$").unwrap();
+    }
+
+    let (name, regexp) = match NAMED.captures(regexp) {
+      Some(captures) => (captures.get(1).unwrap().as_str(), captures.get(2).unwrap().as_str()),
+      None => ("custom", regexp),
+    };
+
+    Pattern {
+      name,
+      regexp,
+      priority: DEFAULT_PRIORITY,
+      transform: None,
+    }
+  }
+}
+
+/// Whether `name` is a builtin pattern, `hyperlink` or one of the `custom` ones
+pub fn known(custom: &[Pattern], name: &str) -> bool {
+  name == "hyperlink"
+    || PATTERNS.iter().any(|tuple| tuple.0 == name)
+    || custom.iter().any(|pattern| pattern.name == name)
+}
+
+/// A pattern ready to match, compiled once per `State`
+struct Compiled<'a> {
+  name: &'a str,
+  priority: i32,
+  regex: Regex,
+  transform: Option<&'a str>,
+  wrap: bool,
+}
+
+/// Where the escape sequence after `start` was, or the end of the line. Matches never cross it.
+fn boundary(boundaries: &[usize], start: usize, len: usize) -> usize {
+  boundaries
+    .get(boundaries.partition_point(|boundary| *boundary <= start))
+    .map_or(len, |boundary| *boundary)
+}
+
+/// First non empty match of `regex` from `start` that doesn't overlap any `taken` range, sorted by start.
+/// Matches never cross the `boundaries` where escape sequences were.
+fn find_free(
+  regex: &Regex,
+  line: &str,
+  mut start: usize,
+  taken: &[(usize, usize)],
+  boundaries: &[usize],
+) -> Option<(usize, usize)> {
+  while start <= line.len() {
+    let boundary = boundary(boundaries, start, line.len());
+
+    let matching = match regex.find_at(&line[..boundary], start) {
+      Some(matching) => matching,
+      None if boundary < line.len() => {
+        start = boundary;
+        continue;
+      }
+      None => return None,
+    };
+
+    if matching.as_str().is_empty() {
+      start = matching.end() + line[matching.end()..].chars().next().map_or(1, |c| c.len_utf8());
+      continue;
+    }
+
+    let overlap = taken.partition_point(|(_, end)| *end <= matching.start());
+
+    match taken.get(overlap) {
+      Some((taken_start, taken_end)) if *taken_start < matching.end() => start = *taken_end,
+      _ => return Some((matching.start(), matching.end())),
+    }
+  }
+
+  None
+}
+
+/// Where an accepted match of a line comes from
+enum Found<'p, 'a> {
+  Pattern(&'p Compiled<'a>),
+  Hyperlink(&'a str),
+}
+
+/// Patterns searched together
+struct Search<'a> {
+  patterns: Vec<Compiled<'a>>,
+  hyperlinks: bool,
+  /// A single pass over each line tells which patterns are worth searching
+  set: RegexSet,
+  priorities: Vec<i32>,
+}
+
+impl<'a> Search<'a> {
+  fn new(patterns: Vec<Compiled<'a>>, hyperlinks: bool) -> Search<'a> {
+    let set = RegexSet::new(patterns.iter().map(|pattern| pattern.regex.as_str())).unwrap();
+
+    let mut priorities = patterns.iter().map(|pattern| pattern.priority).collect::<Vec<_>>();
+    if hyperlinks {
+      priorities.push(HYPERLINK_PRIORITY);
+    }
+    priorities.sort_unstable_by(|x, y| y.cmp(x));
+    priorities.dedup();
+
+    Search {
+      patterns,
+      hyperlinks,
+      set,
+      priorities,
+    }
   }
 }
 
+/// What to match besides the built-in patterns, and how. Nothing by default.
+#[derive(Default)]
+pub struct Options<'a> {
+  /// Patterns from `--regexp` or the patterns file
+  pub custom: &'a [Pattern<'a>],
+  pub disabled: &'a [&'a str],
+  /// When not empty, only the patterns with these names are matched
+  pub only: &'a [&'a str],
+  /// Patterns whose matches may go on in the next line
+  pub wrapped: &'a [&'a str],
+  /// Width of the pane, to tell the wrapped lines
+  pub width: Option<usize>,
+  /// Hints of the previous run, to give them back
+  pub previous: &'a [Entry],
+}
+
 pub struct State<'a> {
-  pub lines: &'a Vec<&'a str>,
-  alphabet: &'a str,
-  regexp: &'a Vec<&'a str>,
+  pub lines: &'a [Line<'a>],
+  alphabet: Alphabet<'a>,
+  search: Search<'a>,
+  width: Option<usize>,
+  previous: &'a [Entry],
 }
 
 impl<'a> State<'a> {
-  pub fn new(lines: &'a Vec<&'a str>, alphabet: &'a str, regexp: &'a Vec<&'a str>) -> State<'a> {
-    State {
+  pub fn new(lines: &'a [Line<'a>], alphabet: Alphabet<'a>, options: Options<'a>) -> Result<State<'a>, Error> {
+    let Options {
+      custom,
+      disabled,
+      only,
+      wrapped,
+      width,
+      previous,
+    } = options;
+
+    if let Some(name) = disabled
+      .iter()
+      .chain(only)
+      .chain(wrapped)
+      .find(|name| !known(custom, name))
+    {
+      return Err(Error::Argument(format!("Unknown pattern: {}", name)));
+    }
+
+    // Patterns are enabled unless disabled by name. A non empty `only` list narrows them to those names.
+    let enabled = |name: &str| !disabled.contains(&name) && (only.is_empty() || only.contains(&name));
+
+    let custom_patterns = custom
+      .iter()
+      .filter(|pattern| enabled(pattern.name))
+      .map(|pattern| {
+        let regex = Regex::new(pattern.regexp).map_err(|source| Error::Regexp {
+          name: pattern.name.to_string(),
+          source,
+        })?;
+
+        Ok(Compiled {
+          name: pattern.name,
+          priority: pattern.priority,
+          regex,
+          transform: pattern.transform,
+          wrap: wrapped.contains(&pattern.name),
+        })
+      })
+      .collect::<Result<Vec<_>, Error>>()?;
+
+    let builtin_patterns = PATTERNS.iter().filter(|tuple| enabled(tuple.0)).map(|tuple| Compiled {
+      name: tuple.0,
+      priority: tuple.1,
+      regex: Regex::new(tuple.2).unwrap(),
+      transform: None,
+      wrap: wrapped.contains(&tuple.0),
+    });
+
+    // On a full tie, this order decides
+    let patterns = custom_patterns.into_iter().chain(builtin_patterns).collect::<Vec<_>>();
+
+    Ok(State {
       lines,
       alphabet,
-      regexp,
+      search: Search::new(patterns, enabled("hyperlink")),
+      width,
+      previous,
+    })
+  }
+
+  /// Priority of the pattern a match comes from. Runtime searches only have one
+  fn priority(&self, pattern: &str) -> i32 {
+    match pattern {
+      "hyperlink" => HYPERLINK_PRIORITY,
+      _ => self
+        .search
+        .patterns
+        .iter()
+        .find(|compiled| compiled.name == pattern)
+        .map_or(DEFAULT_PRIORITY, |compiled| compiled.priority),
     }
   }
 
-  pub fn matches(&self, reverse: bool, unique: bool) -> Vec<Match<'a>> {
-    let mut matches = Vec::new();
+  /// Lines filling the whole pane width may continue on the next one
+  fn is_full(&self, line: &str) -> bool {
+    match self.width {
+      Some(width) if width > 0 => !line.is_empty() && line.width() % width == 0,
+      _ => false,
+    }
+  }
 
-    let exclude_patterns = EXCLUDE_PATTERNS
-      .iter()
-      .map(|tuple| (tuple.0, Regex::new(tuple.1).unwrap()))
-      .collect::<Vec<_>>();
+  /// Follows a match ending a full line onto the next lines, as long as its pattern keeps matching.
+  /// Returns the `(y, start, end)` pieces of the match on every line.
+  fn follow(&self, pattern: &Compiled, index: usize, start: usize, end: usize) -> Vec<(usize, usize, usize)> {
+    let line = &self.lines[index].text;
+    let mut segments = vec![(index, start, end)];
 
-    let custom_patterns = self
-      .regexp
-      .iter()
-      .map(|regexp| ("custom", Regex::new(regexp).expect("Invalid custom regexp")))
-      .collect::<Vec<_>>();
+    if !pattern.wrap || end != line.len() || !self.is_full(line) {
+      return segments;
+    }
 
-    let patterns = PATTERNS
-      .iter()
-      .map(|tuple| (tuple.0, Regex::new(tuple.1).unwrap()))
-      .collect::<Vec<_>>();
+    let mut joined = line[start..end].to_string();
+
+    for (y, next) in self.lines.iter().enumerate().skip(index + 1) {
+      let next = &next.text;
+      let boundary = self.lines[y]
+        .escapes
+        .iter()
+        .map(|(at, _)| *at)
+        .find(|at| *at > 0)
+        .unwrap_or(next.len());
 
-    // This order determines the priority of pattern matching
-    let all_patterns = [exclude_patterns, custom_patterns, patterns].concat();
+      let offset = joined.len();
+      joined.push_str(&next[..boundary]);
+
+      match pattern.regex.find(&joined) {
+        Some(matching) if matching.start() == 0 && matching.end() > offset => {
+          let end = matching.end() - offset;
+          segments.push((y, 0, end));
+
+          if end != next.len() || !self.is_full(next) {
+            break;
+          }
+        }
+        _ => break,
+      }
+    }
+
+    segments
+  }
+
+  /// Screen spans of the `start..end` range of a match laid over its line `segments`
+  fn spans(&self, segments: &[(usize, usize, usize)], start: usize, end: usize) -> Vec<Span<'a>> {
+    let mut spans = Vec::new();
+    let mut offset = 0;
+
+    for (y, segment_start, segment_end) in segments {
+      let length = segment_end - segment_start;
+      let from = start.max(offset);
+      let to = end.min(offset + length);
+
+      // An empty range still gets a place on the screen
+      if from < to || (start == end && from == to && spans.is_empty()) {
+        let x = segment_start + from - offset;
+
+        spans.push(Span {
+          x: x as i32,
+          y: *y as i32,
+          text: &self.lines[*y].text[x..x + to - from],
+        });
+      }
+
+      offset += length;
+    }
+
+    spans
+  }
+
+  pub fn matches(&self, reverse: bool, unique: bool, fixed_length: bool) -> Vec<Match<'a>> {
+    let mut matches = self.find(&self.search);
+
+    // Matches of the previous run get their hints back: first the ones still on the same line, then the
+    // ones sharing their text only
+    for same_line in [true, false] {
+      for index in 0..matches.len() {
+        if matches[index].hint.is_some() {
+          continue;
+        }
+
+        let line = line_hash(&self.lines[matches[index].spans[0].y as usize].text);
+        let entry = self.previous.iter().find(|entry| {
+          entry.text == matches[index].text
+            && (entry.line == line || !same_line)
+            && !matches.iter().any(|mat| mat.hint.as_ref() == Some(&entry.hint))
+            && self.alphabet.spells(&entry.hint)
+        });
+
+        if let Some(entry) = entry {
+          matches[index].hint = Some(entry.hint.clone());
+
+          // Unique matches share their hint
+          if unique {
+            let text = matches[index].text.clone();
+
+            for mat in matches.iter_mut().filter(|mat| mat.text == text) {
+              mat.hint = Some(entry.hint.clone());
+            }
+          }
+        }
+      }
+    }
+
+    self.hint(matches, reverse, unique, fixed_length)
+  }
+
+  /// Matches of a single regexp typed at runtime, instead of the patterns
+  pub fn search(
+    &self,
+    regexp: &str,
+    reverse: bool,
+    unique: bool,
+    fixed_length: bool,
+  ) -> Result<Vec<Match<'a>>, regex::Error> {
+    let pattern = Compiled {
+      name: "search",
+      priority: DEFAULT_PRIORITY,
+      regex: Regex::new(regexp)?,
+      transform: None,
+      wrap: false,
+    };
+
+    let matches = self.find(&Search::new(vec![pattern], false));
+
+    Ok(self.hint(matches, reverse, unique, fixed_length))
+  }
+
+  /// Matches of the patterns, without hints yet
+  fn find(&self, search: &Search<'a>) -> Vec<Match<'a>> {
+    let mut matches = Vec::new();
+
+    // Where the last wrapped match ended, as the line and the byte after it
+    let mut followed = (0, 0);
 
     for (index, line) in self.lines.iter().enumerate() {
-      let mut chunk: &str = line;
-      let mut offset: i32 = 0;
+      if index < followed.0 {
+        continue;
+      }
+
+      let boundaries = line.escapes.iter().map(|(at, _)| *at).collect::<Vec<_>>();
+      let links = if search.hyperlinks { line.links() } else { Vec::new() };
+      let line: &'a str = &line.text;
+      let candidates = search.set.matches(line);
 
-      loop {
-        // For this line we search which patterns match, all of them.
-        let submatches = all_patterns
+      if !candidates.matched_any() && links.is_empty() {
+        continue;
+      }
+
+      // The beginning of the line may belong to a match from the previous one
+      let reserved = if index == followed.0 && followed.1 > 0 {
+        Some((0, followed.1))
+      } else {
+        None
+      };
+
+      let mut accepted: Vec<(Found, usize, usize)> = Vec::new();
+
+      // Overlapping matches are resolved by the highest priority, then the leftmost one, then the longest one.
+      for priority in search.priorities.iter() {
+        if *priority == HYPERLINK_PRIORITY {
+          for (start, end, uri) in links.iter() {
+            let overlaps = accepted
+              .iter()
+              .map(|(_, start, end)| (*start, *end))
+              .chain(reserved)
+              .any(|(taken_start, taken_end)| taken_start < *end && *start < taken_end);
+
+            if !overlaps {
+              accepted.push((Found::Hyperlink(uri), *start, *end));
+            }
+          }
+        }
+
+        let group = search
+          .patterns
           .iter()
-          .filter_map(|tuple| match tuple.1.find_iter(chunk).nth(0) {
-            Some(m) => Some((tuple.0, tuple.1.clone(), m)),
-            None => None,
-          })
+          .enumerate()
+          .filter(|(index, pattern)| pattern.priority == *priority && candidates.matched(*index))
+          .map(|(_, pattern)| pattern)
           .collect::<Vec<_>>();
 
-        // Then, we search for the match with the lowest index
-        let first_match_option = submatches.iter().min_by(|x, y| x.2.start().cmp(&y.2.start()));
-
-        if let Some(first_match) = first_match_option {
-          let (name, pattern, matching) = first_match;
-          let text = matching.as_str();
-
-          if let Some(captures) = pattern.captures(text) {
-            let captures: Vec<(&str, usize)> = if let Some(capture) = captures.name("match") {
-              [(capture.as_str(), capture.start())].to_vec()
-            } else if captures.len() > 1 {
-              captures
-                .iter()
-                .skip(1)
-                .filter_map(|capture| capture)
-                .map(|capture| (capture.as_str(), capture.start()))
-                .collect::<Vec<(&str, usize)>>()
-            } else {
-              [(matching.as_str(), 0)].to_vec()
-            };
-
-            // Never hint or broke bash color sequences, but process it
-            if *name != "bash" {
-              for (subtext, substart) in captures.iter() {
-                matches.push(Match {
-                  x: offset + matching.start() as i32 + *substart as i32,
-                  y: index as i32,
-                  pattern: name,
-                  text: subtext,
-                  hint: None,
-                });
+        if group.is_empty() {
+          continue;
+        }
+
+        // Matches of higher priorities
+        let mut taken = accepted
+          .iter()
+          .map(|(_, start, end)| (*start, *end))
+          .chain(reserved)
+          .collect::<Vec<_>>();
+        taken.sort_unstable();
+
+        // Next match of each pattern, searched again only when it overlaps an accepted one
+        let mut next = group
+          .iter()
+          .map(|pattern| find_free(&pattern.regex, line, 0, &taken, &boundaries))
+          .collect::<Vec<_>>();
+
+        loop {
+          let first_match_option = next
+            .iter()
+            .enumerate()
+            .filter_map(|(position, found)| found.map(|(start, end)| (position, start, end)))
+            .min_by(|x, y| x.1.cmp(&y.1).then(y.2.cmp(&x.2)));
+
+          if let Some((position, start, end)) = first_match_option {
+            accepted.push((Found::Pattern(group[position]), start, end));
+
+            for (position, pattern) in group.iter().enumerate() {
+              if let Some((next_start, _)) = next[position] {
+                if next_start < end {
+                  next[position] = find_free(&pattern.regex, line, end, &taken, &boundaries);
+                }
               }
             }
-
-            chunk = chunk.get(matching.end()..).expect("Unknown chunk");
-            offset += matching.end() as i32;
           } else {
-            panic!("No matching?");
+            break;
+          }
+        }
+      }
+
+      accepted.sort_by_key(|(_, start, _)| *start);
+
+      for (found, start, end) in accepted {
+        let pattern = match found {
+          Found::Pattern(pattern) => pattern,
+          Found::Hyperlink(uri) => {
+            // The hint goes over the visible text, but the hidden URI is copied
+            matches.push(Match {
+              spans: self.spans(&[(index, start, end)], 0, end - start),
+              pattern: "hyperlink",
+              text: Cow::Borrowed(&line[start..end]),
+              copy: uri.to_string(),
+              hint: None,
+            });
+
+            continue;
           }
+        };
+
+        let segments = self.follow(pattern, index, start, end);
+
+        let text: Cow<'a, str> = if segments.len() == 1 {
+          Cow::Borrowed(&line[start..end])
         } else {
-          break;
+          let (y, _, end) = segments[segments.len() - 1];
+          followed = (y, end);
+
+          Cow::Owned(
+            segments
+              .iter()
+              .map(|(y, start, end)| &self.lines[*y].text[*start..*end])
+              .collect(),
+          )
+        };
+
+        // Captures are searched where the match was found, as assertions like `\b` depend on what surrounds
+        // it. Wrapped matches only have their joined text.
+        let (haystack, offset) = if segments.len() == 1 {
+          (&line[..boundary(&boundaries, start, line.len())], start)
+        } else {
+          (text.as_ref(), 0)
+        };
+
+        let captures = || {
+          pattern
+            .regex
+            .captures_at(haystack, offset)
+            .filter(|captures| captures.get(0).is_some_and(|capture| capture.start() == offset))
+        };
+
+        // Pieces of the text to highlight, as `(start, end, copy)`
+        let pieces: Vec<(usize, usize, String)> = if pattern.regex.captures_len() == 1 && pattern.transform.is_none() {
+          // Without groups nor transform, there is no need for the slower captures search
+          [(0, text.len(), text.to_string())].to_vec()
+        } else if let Some(captures) = captures() {
+          // A transform rewrites the copied text, expanding the capture groups of the match
+          let transformed = pattern.transform.map(|transform| {
+            let mut copy = String::new();
+            captures.expand(transform, &mut copy);
+            copy
+          });
+
+          // Named groups pick what is highlighted (display) and what is copied (copy). The legacy
+          // `match` group stands for both. Without named groups, every group is a separate hint.
+          let display = captures
+            .name("display")
+            .or_else(|| captures.name("match"))
+            .or_else(|| captures.name("copy"));
+
+          let pieces: Vec<(usize, usize, &str)> = if let Some(display) = display {
+            let copy = captures
+              .name("copy")
+              .or_else(|| captures.name("match"))
+              .map_or(text.as_ref(), |capture| capture.as_str());
+
+            [(display.start() - offset, display.end() - offset, copy)].to_vec()
+          } else if captures.len() > 1 {
+            captures
+              .iter()
+              .skip(1)
+              .flatten()
+              .map(|capture| (capture.start() - offset, capture.end() - offset, capture.as_str()))
+              .collect()
+          } else {
+            [(0, text.len(), text.as_ref())].to_vec()
+          };
+
+          pieces
+            .into_iter()
+            .map(|(start, end, copy)| (start, end, transformed.clone().unwrap_or_else(|| copy.to_string())))
+            .collect()
+        } else {
+          // Not found again, it can't be split in pieces
+          continue;
+        };
+
+        for (substart, subend, copy) in pieces {
+          let subtext = match text {
+            Cow::Borrowed(text) => Cow::Borrowed(&text[substart..subend]),
+            Cow::Owned(ref text) => Cow::Owned(text[substart..subend].to_string()),
+          };
+
+          matches.push(Match {
+            spans: self.spans(&segments, substart, subend),
+            pattern: pattern.name,
+            text: subtext,
+            copy,
+            hint: None,
+          });
         }
       }
     }
 
-    let alphabet = super::alphabets::get_alphabet(self.alphabet);
-    let mut hints = alphabet.hints(matches.len());
+    matches
+  }
 
-    // This looks wrong but we do a pop after
-    if !reverse {
-      hints.reverse();
+  /// Gives hints to the matches without one, besides the hints they already have
+  pub fn hint(&self, mut matches: Vec<Match<'a>>, reverse: bool, unique: bool, fixed_length: bool) -> Vec<Match<'a>> {
+    // Unique matches share their hints, so they need less of them
+    let count = if unique {
+      matches
+        .iter()
+        .map(|mat| mat.text.as_ref())
+        .collect::<HashSet<_>>()
+        .len()
     } else {
-      matches.reverse();
-      hints.reverse();
-    }
+      matches.len()
+    };
 
-    if unique {
-      let mut previous: HashMap<&str, String> = HashMap::new();
+    let mut taken = matches.iter().filter_map(|mat| mat.hint.clone()).collect::<Vec<_>>();
+    taken.sort_unstable();
+    taken.dedup();
 
-      for mat in &mut matches {
-        if let Some(previous_hint) = previous.get(mat.text) {
-          mat.hint = Some(previous_hint.clone());
-        } else if let Some(hint) = hints.pop() {
-          mat.hint = Some(hint.to_string().clone());
-          previous.insert(mat.text, hint.to_string().clone());
-        }
-      }
+    let restored = taken.iter().map(|hint| hint.as_str()).collect::<Vec<_>>();
+
+    let besides = if restored.is_empty() {
+      None
     } else {
+      self
+        .alphabet
+        .hints_besides(count - restored.len(), &restored, fixed_length)
+    };
+
+    // Without room for new hints, all of them are new
+    if besides.is_none() {
       for mat in &mut matches {
-        if let Some(hint) = hints.pop() {
-          mat.hint = Some(hint.to_string().clone());
-        }
+        mat.hint = None;
       }
     }
 
+    let hints = besides.unwrap_or_else(|| {
+      if fixed_length {
+        self.alphabet.fixed_hints(count)
+      } else {
+        self.alphabet.hints(count)
+      }
+    });
+
+    // The shortest hints come first, for the matches most likely to be picked: the ones of the patterns with
+    // the highest priority, and among them the first ones, or the ones nearest to the bottom of the pane in
+    // reverse. The sort is stable, so it keeps this order on ties
+    let mut ranked = (0..matches.len()).collect::<Vec<_>>();
     if reverse {
-      matches.reverse();
+      ranked.reverse();
+    }
+    ranked.sort_by_key(|&index| Reverse(self.priority(matches[index].pattern)));
+
+    let mut hints = hints.into_iter();
+    let mut previous: HashMap<String, String> = HashMap::new();
+
+    for index in ranked {
+      let mat = &mut matches[index];
+
+      if mat.hint.is_some() {
+        continue;
+      }
+
+      // Unique matches share their hints
+      if let Some(previous_hint) = previous.get(mat.text.as_ref()).filter(|_| unique) {
+        mat.hint = Some(previous_hint.clone());
+      } else if let Some(hint) = hints.next() {
+        previous.insert(mat.text.to_string(), hint.clone());
+        mat.hint = Some(hint);
+      }
     }
 
     matches
   }
+
+  /// Tells if the key types a letter of the hints
+  pub fn is_hint_key(&self, key: char) -> bool {
+    self.alphabet.spells(&key.to_string())
+  }
+
+  /// Hints given to these matches, to give them back in the next run
+  pub fn remember(&self, matches: &[Match]) -> Vec<Entry> {
+    let mut seen = HashSet::new();
+
+    matches
+      .iter()
+      .filter_map(|mat| {
+        let hint = mat.hint.as_ref().filter(|hint| seen.insert(hint.as_str()))?;
+
+        Some(Entry {
+          hint: hint.clone(),
+          line: line_hash(&self.lines[mat.spans[0].y as usize].text),
+          text: mat.text.to_string(),
+        })
+      })
+      .collect()
+  }
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
 
-  fn split(output: &str) -> Vec<&str> {
-    output.split("\n").collect::<Vec<&str>>()
+  fn split(output: &str) -> Vec<Line<'_>> {
+    output.split("\n").map(Line::parse).collect::<Vec<Line>>()
+  }
+
+  fn new_state<'a>(lines: &'a [Line<'a>], alphabet: &'a str, options: Options<'a>) -> State<'a> {
+    State::new(lines, Alphabet::new(alphabet), options).unwrap()
   }
 
   #[test]
   fn match_reverse() {
     let lines = split("lorem 127.0.0.1 lorem 255.255.255.255 lorem 127.0.0.1 lorem");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = new_state(&lines, "abcd", Options::default()).matches(false, false, false);
 
     assert_eq!(results.len(), 3);
     assert_eq!(results.first().unwrap().hint.clone().unwrap(), "a");
@@ -210,23 +773,219 @@ mod tests {
   #[test]
   fn match_unique() {
     let lines = split("lorem 127.0.0.1 lorem 255.255.255.255 lorem 127.0.0.1 lorem");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, true);
+    let results = new_state(&lines, "abcd", Options::default()).matches(false, true, false);
+
+    assert_eq!(results.len(), 3);
+    assert_eq!(results.first().unwrap().hint.clone().unwrap(), "a");
+    assert_eq!(results.last().unwrap().hint.clone().unwrap(), "a");
+  }
+
+  #[test]
+  fn match_unique_short_hints() {
+    let lines = split("lorem 127.0.0.1 lorem 255.255.255.255 lorem 127.0.0.1 lorem");
+    let results = new_state(&lines, "ab", Options::default()).matches(true, true, false);
 
     assert_eq!(results.len(), 3);
     assert_eq!(results.first().unwrap().hint.clone().unwrap(), "a");
+    assert_eq!(results.get(1).unwrap().hint.clone().unwrap(), "b");
+    assert_eq!(results.last().unwrap().hint.clone().unwrap(), "a");
+  }
+
+  #[test]
+  fn match_priority_short_hints() {
+    let lines = split("127.0.0.1 lorem 10.0.0.1\nlorem JIRA-1234");
+    let custom = [Pattern::parse("name:jira=JIRA-[0-9]+")].to_vec();
+    let results = new_state(
+      &lines,
+      "ab",
+      Options {
+        custom: &custom,
+        ..Default::default()
+      },
+    )
+    .matches(false, false, false);
+
+    assert_eq!(results.len(), 3);
     assert_eq!(results.last().unwrap().hint.clone().unwrap(), "a");
+    assert_eq!(results.first().unwrap().hint.clone().unwrap(), "ba");
+    assert_eq!(results.get(1).unwrap().hint.clone().unwrap(), "bb");
+  }
+
+  #[test]
+  fn match_nearest_short_hints() {
+    let lines = split("127.0.0.1\n10.0.0.1\n192.168.0.1");
+    let results = new_state(&lines, "ab", Options::default()).matches(true, false, false);
+
+    assert_eq!(results.len(), 3);
+    assert_eq!(results.last().unwrap().hint.clone().unwrap(), "a");
+    assert_eq!(results.get(1).unwrap().hint.clone().unwrap(), "ba");
+    assert_eq!(results.first().unwrap().hint.clone().unwrap(), "bb");
+  }
+
+  #[test]
+  fn match_fixed_length() {
+    let lines = split("lorem 127.0.0.1 lorem 255.255.255.255 lorem 127.0.0.1 lorem");
+    let results = new_state(&lines, "ab", Options::default()).matches(false, false, true);
+
+    assert_eq!(results.len(), 3);
+    assert_eq!(results.first().unwrap().hint.clone().unwrap(), "aa");
+    assert_eq!(results.last().unwrap().hint.clone().unwrap(), "ba");
+  }
+
+  #[test]
+  fn match_stable_hints() {
+    let lines = split("lorem 127.0.0.1 lorem 10.0.0.1");
+    let state = new_state(&lines, "abcd", Options::default());
+    let previous = state.remember(&state.matches(false, false, false));
+
+    let lines = split("lorem 192.168.0.1 lorem 10.0.0.1\nlorem 127.0.0.1 lorem 10.0.0.1");
+    let results = new_state(
+      &lines,
+      "abcd",
+      Options {
+        previous: &previous,
+        ..Default::default()
+      },
+    )
+    .matches(false, false, false);
+
+    assert_eq!(results.len(), 4);
+    assert_eq!(results.first().unwrap().hint.clone().unwrap(), "c");
+    assert_eq!(results.get(1).unwrap().hint.clone().unwrap(), "d");
+    assert_eq!(results.get(2).unwrap().hint.clone().unwrap(), "a");
+    assert_eq!(results.get(3).unwrap().hint.clone().unwrap(), "b");
+  }
+
+  #[test]
+  fn match_stable_unique_hints() {
+    let lines = split("lorem 127.0.0.1 lorem 10.0.0.1");
+    let state = new_state(&lines, "abcd", Options::default());
+    let previous = state.remember(&state.matches(false, true, false));
+
+    let lines = split("lorem 10.0.0.1 lorem 192.168.0.1\nlorem 127.0.0.1 lorem 10.0.0.1");
+    let results = new_state(
+      &lines,
+      "abcd",
+      Options {
+        previous: &previous,
+        ..Default::default()
+      },
+    )
+    .matches(false, true, false);
+
+    assert_eq!(results.len(), 4);
+    assert_eq!(results.first().unwrap().hint.clone().unwrap(), "b");
+    assert_eq!(results.get(1).unwrap().hint.clone().unwrap(), "c");
+    assert_eq!(results.get(2).unwrap().hint.clone().unwrap(), "a");
+    assert_eq!(results.get(3).unwrap().hint.clone().unwrap(), "b");
+  }
+
+  #[test]
+  fn match_stable_hints_without_room() {
+    let lines = split("lorem 127.0.0.1 lorem 10.0.0.1");
+    let state = new_state(&lines, "ab", Options::default());
+    let previous = state.remember(&state.matches(false, false, false));
+
+    let lines = split("lorem 192.168.0.1 lorem 127.0.0.1 lorem 10.0.0.1");
+    let results = new_state(
+      &lines,
+      "ab",
+      Options {
+        previous: &previous,
+        ..Default::default()
+      },
+    )
+    .matches(false, false, false);
+
+    assert_eq!(results.len(), 3);
+    assert_eq!(results.first().unwrap().hint.clone().unwrap(), "a");
+    assert_eq!(results.get(1).unwrap().hint.clone().unwrap(), "ba");
+    assert_eq!(results.get(2).unwrap().hint.clone().unwrap(), "bb");
+  }
+
+  #[test]
+  fn invalid_custom_regexp() {
+    let lines = split("lorem");
+    let custom = ["name:bad=(lorem"]
+      .iter()
+      .map(|regexp| Pattern::parse(regexp))
+      .collect::<Vec<_>>();
+    let result = State::new(
+      &lines,
+      Alphabet::new("abcd"),
+      Options {
+        custom: &custom,
+        ..Default::default()
+      },
+    );
+
+    assert!(matches!(result, Err(Error::Regexp { ref name, .. }) if name == "bad"));
+  }
+
+  #[test]
+  fn unknown_pattern_names() {
+    let lines = split("lorem");
+    let custom = [Pattern::parse("name:jira=JIRA-[0-9]+")].to_vec();
+
+    for (disabled, only, wrapped) in [
+      (&["wat"][..], &[][..], &[][..]),
+      (&[], &["wat"], &[]),
+      (&[], &[], &["wat"]),
+    ] {
+      let result = State::new(
+        &lines,
+        Alphabet::new("abcd"),
+        Options {
+          custom: &custom,
+          disabled,
+          only,
+          wrapped,
+          ..Default::default()
+        },
+      );
+
+      assert!(matches!(result, Err(Error::Argument(ref message)) if message == "Unknown pattern: wat"));
+    }
+
+    let known = ["jira", "url", "hyperlink"];
+    assert!(known.iter().all(|name| super::known(&custom, name)));
+    assert!(!super::known(&custom, "wat"));
+    assert!(State::new(
+      &lines,
+      Alphabet::new("abcd"),
+      Options {
+        custom: &custom,
+        disabled: &known,
+        only: &known,
+        wrapped: &known,
+        ..Default::default()
+      }
+    )
+    .is_ok());
+  }
+
+  #[test]
+  fn search_regexp() {
+    let lines = split("JIRA-12 and JIRA-345 at 127.0.0.1");
+    let state = new_state(&lines, "abcd", Options::default());
+    let results = state.search(r"JIRA-\d+", false, false, false).unwrap();
+
+    assert_eq!(results.len(), 2);
+    assert_eq!(results.first().unwrap().text, "JIRA-12");
+    assert_eq!(results.first().unwrap().pattern, "search");
+    assert_eq!(results.first().unwrap().hint.clone().unwrap(), "a");
+    assert_eq!(results.last().unwrap().text, "JIRA-345");
+    assert!(state.search("(JIRA", false, false, false).is_err());
   }
 
   #[test]
   fn match_docker() {
     let lines = split("latest sha256:30557a29d5abc51e5f1d5b472e79b7e296f595abcf19fe6b9199dbbc809c6ff4 20 hours ago");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = new_state(&lines, "abcd", Options::default()).matches(false, false, false);
 
     assert_eq!(results.len(), 1);
     assert_eq!(
-      results.get(0).unwrap().text,
+      results.first().unwrap().text,
       "30557a29d5abc51e5f1d5b472e79b7e296f595abcf19fe6b9199dbbc809c6ff4"
     );
   }
@@ -234,63 +993,104 @@ mod tests {
   #[test]
   fn match_bash() {
     let lines = split("path: [32m/var/log/nginx.log[m\npath: [32mtest/log/nginx-2.log:32[mfolder/.nginx@4df2.log");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = new_state(&lines, "abcd", Options::default()).matches(false, false, false);
 
     assert_eq!(results.len(), 3);
-    assert_eq!(results.get(0).unwrap().text, "/var/log/nginx.log");
+    assert_eq!(results.first().unwrap().text, "/var/log/nginx.log");
     assert_eq!(results.get(1).unwrap().text, "test/log/nginx-2.log");
     assert_eq!(results.get(2).unwrap().text, "folder/.nginx@4df2.log");
   }
 
+  #[test]
+  fn match_escape_sequences() {
+    let lines = split("\x1b[38;5;208m/var/log\x1b[0m\x1b[K \x1b]8;;file:///tmp\x1b\\127.0.0.1\x1b]8;;\x1b\\");
+    let results = new_state(&lines, "abcd", Options::default()).matches(false, false, false);
+
+    assert_eq!(results.len(), 2);
+    assert_eq!(results.first().unwrap().text, "/var/log");
+    assert_eq!(results.first().unwrap().spans[0].x, 0);
+    assert_eq!(results.last().unwrap().text, "127.0.0.1");
+    assert_eq!(results.last().unwrap().spans[0].x, 9);
+  }
+
+  #[test]
+  fn match_hyperlinks() {
+    let lines = split(
+      "ls \x1b]8;;file:///tmp/foo.txt\x1b\\foo.txt\x1b]8;;\x1b\\ \x1b]8;;https://bar.baz\x07/var/bar\x1b]8;;\x07",
+    );
+    let results = new_state(&lines, "abcd", Options::default()).matches(false, false, false);
+
+    assert_eq!(results.len(), 2);
+    assert_eq!(results.first().unwrap().pattern, "hyperlink");
+    assert_eq!(results.first().unwrap().text, "foo.txt");
+    assert_eq!(results.first().unwrap().copy, "file:///tmp/foo.txt");
+    assert_eq!(results.first().unwrap().spans[0].x, 3);
+    assert_eq!(results.last().unwrap().pattern, "hyperlink");
+    assert_eq!(results.last().unwrap().text, "/var/bar");
+    assert_eq!(results.last().unwrap().copy, "https://bar.baz");
+  }
+
+  #[test]
+  fn match_disabled_hyperlinks() {
+    let lines = split("ls \x1b]8;;https://bar.baz\x07/var/bar\x1b]8;;\x07");
+    let results = new_state(
+      &lines,
+      "abcd",
+      Options {
+        disabled: &["hyperlink"],
+        ..Default::default()
+      },
+    )
+    .matches(false, false, false);
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(results.first().unwrap().pattern, "path");
+    assert_eq!(results.first().unwrap().copy, "/var/bar");
+  }
+
   #[test]
   fn match_paths() {
     let lines = split("Lorem /tmp/foo/bar_lol, lorem\n Lorem /var/log/boot-strap.log lorem ../log/kern.log lorem");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = new_state(&lines, "abcd", Options::default()).matches(false, false, false);
 
     assert_eq!(results.len(), 3);
-    assert_eq!(results.get(0).unwrap().text.clone(), "/tmp/foo/bar_lol");
-    assert_eq!(results.get(1).unwrap().text.clone(), "/var/log/boot-strap.log");
-    assert_eq!(results.get(2).unwrap().text.clone(), "../log/kern.log");
+    assert_eq!(results.first().unwrap().text, "/tmp/foo/bar_lol");
+    assert_eq!(results.get(1).unwrap().text, "/var/log/boot-strap.log");
+    assert_eq!(results.get(2).unwrap().text, "../log/kern.log");
   }
 
   #[test]
   fn match_routes() {
     let lines = split("Lorem /app/routes/$routeId/$objectId, lorem\n Lorem /app/routes/$sectionId");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = new_state(&lines, "abcd", Options::default()).matches(false, false, false);
 
     assert_eq!(results.len(), 2);
-    assert_eq!(results.get(0).unwrap().text.clone(), "/app/routes/$routeId/$objectId");
-    assert_eq!(results.get(1).unwrap().text.clone(), "/app/routes/$sectionId");
+    assert_eq!(results.first().unwrap().text, "/app/routes/$routeId/$objectId");
+    assert_eq!(results.get(1).unwrap().text, "/app/routes/$sectionId");
   }
 
   #[test]
   fn match_home() {
     let lines = split("Lorem ~/.gnu/.config.txt, lorem");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = new_state(&lines, "abcd", Options::default()).matches(false, false, false);
 
     assert_eq!(results.len(), 1);
-    assert_eq!(results.get(0).unwrap().text.clone(), "~/.gnu/.config.txt");
+    assert_eq!(results.first().unwrap().text, "~/.gnu/.config.txt");
   }
 
   #[test]
   fn match_slugs() {
     let lines = split("Lorem dev/api/[slug]/foo, lorem");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = new_state(&lines, "abcd", Options::default()).matches(false, false, false);
 
     assert_eq!(results.len(), 1);
-    assert_eq!(results.get(0).unwrap().text.clone(), "dev/api/[slug]/foo");
+    assert_eq!(results.first().unwrap().text, "dev/api/[slug]/foo");
   }
 
   #[test]
   fn match_uids() {
     let lines = split("Lorem ipsum 123e4567-e89b-12d3-a456-426655440000 lorem\n Lorem lorem lorem");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = new_state(&lines, "abcd", Options::default()).matches(false, false, false);
 
     assert_eq!(results.len(), 1);
   }
@@ -298,111 +1098,97 @@ mod tests {
   #[test]
   fn match_shas() {
     let lines = split("Lorem fd70b5695 5246ddf f924213 lorem\n Lorem 973113963b491874ab2e372ee60d4b4cb75f717c lorem");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = new_state(&lines, "abcd", Options::default()).matches(false, false, false);
 
     assert_eq!(results.len(), 4);
-    assert_eq!(results.get(0).unwrap().text.clone(), "fd70b5695");
-    assert_eq!(results.get(1).unwrap().text.clone(), "5246ddf");
-    assert_eq!(results.get(2).unwrap().text.clone(), "f924213");
-    assert_eq!(
-      results.get(3).unwrap().text.clone(),
-      "973113963b491874ab2e372ee60d4b4cb75f717c"
-    );
+    assert_eq!(results.first().unwrap().text, "fd70b5695");
+    assert_eq!(results.get(1).unwrap().text, "5246ddf");
+    assert_eq!(results.get(2).unwrap().text, "f924213");
+    assert_eq!(results.get(3).unwrap().text, "973113963b491874ab2e372ee60d4b4cb75f717c");
   }
 
   #[test]
   fn match_ips() {
     let lines = split("Lorem ipsum 127.0.0.1 lorem\n Lorem 255.255.10.255 lorem 127.0.0.1 lorem");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = new_state(&lines, "abcd", Options::default()).matches(false, false, false);
 
     assert_eq!(results.len(), 3);
-    assert_eq!(results.get(0).unwrap().text.clone(), "127.0.0.1");
-    assert_eq!(results.get(1).unwrap().text.clone(), "255.255.10.255");
-    assert_eq!(results.get(2).unwrap().text.clone(), "127.0.0.1");
+    assert_eq!(results.first().unwrap().text, "127.0.0.1");
+    assert_eq!(results.get(1).unwrap().text, "255.255.10.255");
+    assert_eq!(results.get(2).unwrap().text, "127.0.0.1");
   }
 
   #[test]
   fn match_ipv6s() {
     let lines = split("Lorem ipsum fe80::2:202:fe4 lorem\n Lorem 2001:67c:670:202:7ba8:5e41:1591:d723 lorem fe80::2:1 lorem ipsum fe80:22:312:fe::1%eth0");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = new_state(&lines, "abcd", Options::default()).matches(false, false, false);
 
     assert_eq!(results.len(), 4);
-    assert_eq!(results.get(0).unwrap().text.clone(), "fe80::2:202:fe4");
-    assert_eq!(
-      results.get(1).unwrap().text.clone(),
-      "2001:67c:670:202:7ba8:5e41:1591:d723"
-    );
-    assert_eq!(results.get(2).unwrap().text.clone(), "fe80::2:1");
-    assert_eq!(results.get(3).unwrap().text.clone(), "fe80:22:312:fe::1%eth0");
+    assert_eq!(results.first().unwrap().text, "fe80::2:202:fe4");
+    assert_eq!(results.get(1).unwrap().text, "2001:67c:670:202:7ba8:5e41:1591:d723");
+    assert_eq!(results.get(2).unwrap().text, "fe80::2:1");
+    assert_eq!(results.get(3).unwrap().text, "fe80:22:312:fe::1%eth0");
   }
 
   #[test]
   fn match_markdown_urls() {
     let lines = split("Lorem ipsum [link](https://github.io?foo=bar) ![](http://cdn.com/img.jpg) lorem");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = new_state(&lines, "abcd", Options::default()).matches(false, false, false);
 
     assert_eq!(results.len(), 2);
-    assert_eq!(results.get(0).unwrap().pattern.clone(), "markdown_url");
-    assert_eq!(results.get(0).unwrap().text.clone(), "https://github.io?foo=bar");
-    assert_eq!(results.get(1).unwrap().pattern.clone(), "markdown_url");
-    assert_eq!(results.get(1).unwrap().text.clone(), "http://cdn.com/img.jpg");
+    assert_eq!(results.first().unwrap().pattern, "markdown_url");
+    assert_eq!(results.first().unwrap().text, "https://github.io?foo=bar");
+    assert_eq!(results.get(1).unwrap().pattern, "markdown_url");
+    assert_eq!(results.get(1).unwrap().text, "http://cdn.com/img.jpg");
   }
 
   #[test]
   fn match_urls() {
     let lines = split("Lorem ipsum https://www.rust-lang.org/tools lorem\n Lorem ipsumhttps://crates.io lorem https://github.io?foo=bar lorem ssh://github.io");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = new_state(&lines, "abcd", Options::default()).matches(false, false, false);
 
     assert_eq!(results.len(), 4);
-    assert_eq!(results.get(0).unwrap().text.clone(), "https://www.rust-lang.org/tools");
-    assert_eq!(results.get(0).unwrap().pattern.clone(), "url");
-    assert_eq!(results.get(1).unwrap().text.clone(), "https://crates.io");
-    assert_eq!(results.get(1).unwrap().pattern.clone(), "url");
-    assert_eq!(results.get(2).unwrap().text.clone(), "https://github.io?foo=bar");
-    assert_eq!(results.get(2).unwrap().pattern.clone(), "url");
-    assert_eq!(results.get(3).unwrap().text.clone(), "ssh://github.io");
-    assert_eq!(results.get(3).unwrap().pattern.clone(), "url");
+    assert_eq!(results.first().unwrap().text, "https://www.rust-lang.org/tools");
+    assert_eq!(results.first().unwrap().pattern, "url");
+    assert_eq!(results.get(1).unwrap().text, "https://crates.io");
+    assert_eq!(results.get(1).unwrap().pattern, "url");
+    assert_eq!(results.get(2).unwrap().text, "https://github.io?foo=bar");
+    assert_eq!(results.get(2).unwrap().pattern, "url");
+    assert_eq!(results.get(3).unwrap().text, "ssh://github.io");
+    assert_eq!(results.get(3).unwrap().pattern, "url");
   }
 
   #[test]
   fn match_addresses() {
     let lines = split("Lorem 0xfd70b5695 0x5246ddf lorem\n Lorem 0x973113tlorem");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = new_state(&lines, "abcd", Options::default()).matches(false, false, false);
 
     assert_eq!(results.len(), 3);
-    assert_eq!(results.get(0).unwrap().text.clone(), "0xfd70b5695");
-    assert_eq!(results.get(1).unwrap().text.clone(), "0x5246ddf");
-    assert_eq!(results.get(2).unwrap().text.clone(), "0x973113");
+    assert_eq!(results.first().unwrap().text, "0xfd70b5695");
+    assert_eq!(results.get(1).unwrap().text, "0x5246ddf");
+    assert_eq!(results.get(2).unwrap().text, "0x973113");
   }
 
   #[test]
   fn match_hex_colors() {
     let lines = split("Lorem #fd7b56 lorem #FF00FF\n Lorem #00fF05 lorem #abcd00 lorem #afRR00");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = new_state(&lines, "abcd", Options::default()).matches(false, false, false);
 
     assert_eq!(results.len(), 4);
-    assert_eq!(results.get(0).unwrap().text.clone(), "#fd7b56");
-    assert_eq!(results.get(1).unwrap().text.clone(), "#FF00FF");
-    assert_eq!(results.get(2).unwrap().text.clone(), "#00fF05");
-    assert_eq!(results.get(3).unwrap().text.clone(), "#abcd00");
+    assert_eq!(results.first().unwrap().text, "#fd7b56");
+    assert_eq!(results.get(1).unwrap().text, "#FF00FF");
+    assert_eq!(results.get(2).unwrap().text, "#00fF05");
+    assert_eq!(results.get(3).unwrap().text, "#abcd00");
   }
 
   #[test]
   fn match_ipfs() {
     let lines = split("Lorem QmRdbNSxDJBXmssAc9fvTtux4duptMvfSGiGuq6yHAQVKQ lorem Qmfoobar");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = new_state(&lines, "abcd", Options::default()).matches(false, false, false);
 
     assert_eq!(results.len(), 1);
     assert_eq!(
-      results.get(0).unwrap().text.clone(),
+      results.first().unwrap().text,
       "QmRdbNSxDJBXmssAc9fvTtux4duptMvfSGiGuq6yHAQVKQ"
     );
   }
@@ -411,8 +1197,7 @@ mod tests {
   fn match_process_port() {
     let lines =
       split("Lorem 5695 52463 lorem\n Lorem 973113 lorem 99999 lorem 8888 lorem\n   23456 lorem 5432 lorem 23444");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = new_state(&lines, "abcd", Options::default()).matches(false, false, false);
 
     assert_eq!(results.len(), 8);
   }
@@ -420,52 +1205,469 @@ mod tests {
   #[test]
   fn match_diff_a() {
     let lines = split("Lorem lorem\n--- a/src/main.rs");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = new_state(&lines, "abcd", Options::default()).matches(false, false, false);
 
     assert_eq!(results.len(), 1);
-    assert_eq!(results.get(0).unwrap().text.clone(), "src/main.rs");
+    assert_eq!(results.first().unwrap().text, "src/main.rs");
   }
 
   #[test]
   fn match_diff_b() {
     let lines = split("Lorem lorem\n+++ b/src/main.rs");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = new_state(&lines, "abcd", Options::default()).matches(false, false, false);
 
     assert_eq!(results.len(), 1);
-    assert_eq!(results.get(0).unwrap().text.clone(), "src/main.rs");
+    assert_eq!(results.first().unwrap().text, "src/main.rs");
   }
 
   #[test]
   fn match_diff_summary() {
     let lines = split("diff --git a/samples/test1 b/samples/test2");
-    let custom = [].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let results = new_state(&lines, "abcd", Options::default()).matches(false, false, false);
 
     assert_eq!(results.len(), 2);
-    assert_eq!(results.get(0).unwrap().text.clone(), "samples/test1");
-    assert_eq!(results.get(1).unwrap().text.clone(), "samples/test2");
+    assert_eq!(results.first().unwrap().text, "samples/test1");
+    assert_eq!(results.get(1).unwrap().text, "samples/test2");
   }
 
   #[test]
   fn priority() {
     let lines = split("Lorem [link](http://foo.bar) ipsum CUSTOM-52463 lorem ISSUE-123 lorem\nLorem /var/fd70b569/9999.log 52463 lorem\n Lorem 973113 lorem 123e4567-e89b-12d3-a456-426655440000 lorem 8888 lorem\n  https://crates.io/23456/fd70b569 lorem");
-    let custom = ["CUSTOM-[0-9]{4,}", "ISSUE-[0-9]{3}"].to_vec();
-    let results = State::new(&lines, "abcd", &custom).matches(false, false);
+    let custom = [Pattern::parse("CUSTOM-[0-9]{4,}"), Pattern::parse("ISSUE-[0-9]{3}")].to_vec();
+    let results = new_state(
+      &lines,
+      "abcd",
+      Options {
+        custom: &custom,
+        ..Default::default()
+      },
+    )
+    .matches(false, false, false);
 
     assert_eq!(results.len(), 9);
-    assert_eq!(results.get(0).unwrap().text.clone(), "http://foo.bar");
-    assert_eq!(results.get(1).unwrap().text.clone(), "CUSTOM-52463");
-    assert_eq!(results.get(2).unwrap().text.clone(), "ISSUE-123");
-    assert_eq!(results.get(3).unwrap().text.clone(), "/var/fd70b569/9999.log");
-    assert_eq!(results.get(4).unwrap().text.clone(), "52463");
-    assert_eq!(results.get(5).unwrap().text.clone(), "973113");
+    assert_eq!(results.first().unwrap().text, "http://foo.bar");
+    assert_eq!(results.get(1).unwrap().text, "CUSTOM-52463");
+    assert_eq!(results.get(1).unwrap().pattern, "custom");
+    assert_eq!(results.get(2).unwrap().text, "ISSUE-123");
+    assert_eq!(results.get(3).unwrap().text, "/var/fd70b569/9999.log");
+    assert_eq!(results.get(4).unwrap().text, "52463");
+    assert_eq!(results.get(5).unwrap().text, "973113");
+    assert_eq!(results.get(6).unwrap().text, "123e4567-e89b-12d3-a456-426655440000");
+    assert_eq!(results.get(7).unwrap().text, "8888");
+    assert_eq!(results.get(8).unwrap().text, "https://crates.io/23456/fd70b569");
+  }
+
+  #[test]
+  fn match_named_regexp() {
+    let lines = split("Lorem JIRA-1234 lorem");
+    let custom = [Pattern::parse("name:jira=JIRA-[0-9]+")].to_vec();
+    let results = new_state(
+      &lines,
+      "abcd",
+      Options {
+        custom: &custom,
+        ..Default::default()
+      },
+    )
+    .matches(false, false, false);
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(results.first().unwrap().pattern, "jira");
+    assert_eq!(results.first().unwrap().text, "JIRA-1234");
+  }
+
+  #[test]
+  fn match_assertion_regexp() {
+    let lines = split("xfoo bar");
+    let custom = [Pattern::parse("name:a=\\B(foo)")].to_vec();
+    let results = new_state(
+      &lines,
+      "abcd",
+      Options {
+        custom: &custom,
+        only: &["a"],
+        ..Default::default()
+      },
+    )
+    .matches(false, false, false);
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(results.first().unwrap().text, "foo");
+  }
+
+  #[test]
+  fn match_key_value_regexp() {
+    let lines = split("Lorem foo=bar id=42 lorem");
+    let custom = [Pattern::parse("foo=bar"), Pattern::parse("id=\\d+")].to_vec();
+    let results = new_state(
+      &lines,
+      "abcd",
+      Options {
+        custom: &custom,
+        ..Default::default()
+      },
+    )
+    .matches(false, false, false);
+
+    assert_eq!(results.len(), 2);
+    assert_eq!(results.first().unwrap().pattern, "custom");
+    assert_eq!(results.first().unwrap().text, "foo=bar");
+    assert_eq!(results.get(1).unwrap().pattern, "custom");
+    assert_eq!(results.get(1).unwrap().text, "id=42");
+  }
+
+  #[test]
+  fn match_copy_group() {
+    let lines = split("Lorem ticket:JIRA-1234 lorem");
+    let custom = [Pattern::parse("name:ticket=ticket:(?P<copy>JIRA-[0-9]+)")].to_vec();
+    let results = new_state(
+      &lines,
+      "abcd",
+      Options {
+        custom: &custom,
+        ..Default::default()
+      },
+    )
+    .matches(false, false, false);
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(results.first().unwrap().spans[0].x, 13);
+    assert_eq!(results.first().unwrap().text, "JIRA-1234");
+    assert_eq!(results.first().unwrap().copy, "JIRA-1234");
+  }
+
+  #[test]
+  fn match_display_and_copy_groups() {
+    let lines = split("Lorem [docs](https://docs.rs) lorem");
+    let custom = [Pattern::parse(
+      "name:link=\\[(?P<display>[^]]*)\\]\\((?P<copy>[^)]+)\\)",
+    )]
+    .to_vec();
+    let results = new_state(
+      &lines,
+      "abcd",
+      Options {
+        custom: &custom,
+        ..Default::default()
+      },
+    )
+    .matches(false, false, false);
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(results.first().unwrap().pattern, "link");
+    assert_eq!(results.first().unwrap().spans[0].x, 7);
+    assert_eq!(results.first().unwrap().text, "docs");
+    assert_eq!(results.first().unwrap().copy, "https://docs.rs");
+  }
+
+  #[test]
+  fn match_display_group() {
+    let lines = split("Lorem PR#42 lorem");
+    let custom = [Pattern::parse("name:pr=PR#(?P<display>[0-9]+)")].to_vec();
+    let results = new_state(
+      &lines,
+      "abcd",
+      Options {
+        custom: &custom,
+        ..Default::default()
+      },
+    )
+    .matches(false, false, false);
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(results.first().unwrap().text, "42");
+    assert_eq!(results.first().unwrap().copy, "PR#42");
+  }
+
+  #[test]
+  fn match_disabled_patterns() {
+    let lines = split("Lorem fd70b5695 lorem 52463 lorem 127.0.0.1");
+    let results = new_state(
+      &lines,
+      "abcd",
+      Options {
+        disabled: &["sha", "number"],
+        ..Default::default()
+      },
+    )
+    .matches(false, false, false);
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(results.first().unwrap().pattern, "ip");
+  }
+
+  #[test]
+  fn match_only_patterns() {
+    let lines = split("Lorem fd70b5695 lorem 52463 lorem JIRA-1234 127.0.0.1");
+    let custom = [Pattern::parse("name:jira=JIRA-[0-9]+")].to_vec();
+    let results = new_state(
+      &lines,
+      "abcd",
+      Options {
+        custom: &custom,
+        only: &["jira", "ip"],
+        ..Default::default()
+      },
+    )
+    .matches(false, false, false);
+
+    assert_eq!(results.len(), 2);
+    assert_eq!(results.first().unwrap().pattern, "jira");
+    assert_eq!(results.get(1).unwrap().pattern, "ip");
+  }
+
+  #[test]
+  fn match_transform() {
+    let lines = split("Lorem JIRA-1234 lorem");
+    let custom = [Pattern {
+      name: "jira",
+      regexp: "(?P<match>JIRA-(?P<id>[0-9]+))",
+      priority: DEFAULT_PRIORITY,
+      transform: Some("https://jira.example.com/browse/JIRA-${id}"),
+    }]
+    .to_vec();
+    let results = new_state(
+      &lines,
+      "abcd",
+      Options {
+        custom: &custom,
+        ..Default::default()
+      },
+    )
+    .matches(false, false, false);
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(results.first().unwrap().text, "JIRA-1234");
     assert_eq!(
-      results.get(6).unwrap().text.clone(),
-      "123e4567-e89b-12d3-a456-426655440000"
+      results.first().unwrap().copy,
+      "https://jira.example.com/browse/JIRA-1234"
     );
-    assert_eq!(results.get(7).unwrap().text.clone(), "8888");
-    assert_eq!(results.get(8).unwrap().text.clone(), "https://crates.io/23456/fd70b569");
+  }
+
+  #[test]
+  fn overlap_path_with_sha() {
+    let lines = split("Lorem /var/fd70b569/log lorem");
+    let results = new_state(&lines, "abcd", Options::default()).matches(false, false, false);
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(results.first().unwrap().pattern, "path");
+  }
+
+  #[test]
+  fn overlap_url_with_number() {
+    let lines = split("Lorem https://crates.io/23456 lorem");
+    let results = new_state(&lines, "abcd", Options::default()).matches(false, false, false);
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(results.first().unwrap().pattern, "url");
+  }
+
+  #[test]
+  fn overlap_highest_priority() {
+    let lines = split("Lorem tmp/JIRA-1234 lorem");
+    let custom = [Pattern::parse("name:jira=JIRA-[0-9]+")].to_vec();
+    let results = new_state(
+      &lines,
+      "abcd",
+      Options {
+        custom: &custom,
+        ..Default::default()
+      },
+    )
+    .matches(false, false, false);
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(results.first().unwrap().pattern, "jira");
+    assert_eq!(results.first().unwrap().text, "JIRA-1234");
+  }
+
+  #[test]
+  fn overlap_lower_priority() {
+    let lines = split("Lorem tmp/JIRA-1234 lorem");
+    let custom = [Pattern {
+      name: "jira",
+      regexp: "JIRA-[0-9]+",
+      priority: 0,
+      transform: None,
+    }]
+    .to_vec();
+    let results = new_state(
+      &lines,
+      "abcd",
+      Options {
+        custom: &custom,
+        ..Default::default()
+      },
+    )
+    .matches(false, false, false);
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(results.first().unwrap().pattern, "path");
+    assert_eq!(results.first().unwrap().text, "tmp/JIRA-1234");
+  }
+
+  #[test]
+  fn overlap_leftmost() {
+    let lines = split("Lorem abcde lorem");
+    let custom = [Pattern::parse("name:second=cde"), Pattern::parse("name:first=abc")].to_vec();
+    let results = new_state(
+      &lines,
+      "abcd",
+      Options {
+        custom: &custom,
+        ..Default::default()
+      },
+    )
+    .matches(false, false, false);
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(results.first().unwrap().pattern, "first");
+  }
+
+  #[test]
+  fn overlap_longest() {
+    let lines = split("Lorem abcde lorem");
+    let custom = [Pattern::parse("name:short=abc"), Pattern::parse("name:long=abcde")].to_vec();
+    let results = new_state(
+      &lines,
+      "abcd",
+      Options {
+        custom: &custom,
+        ..Default::default()
+      },
+    )
+    .matches(false, false, false);
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(results.first().unwrap().pattern, "long");
+  }
+
+  #[test]
+  fn overlap_empty_match() {
+    let lines = split("Lorem 127.0.0.1 lorem");
+    let custom = [Pattern::parse("name:empty=x*")].to_vec();
+    let results = new_state(
+      &lines,
+      "abcd",
+      Options {
+        custom: &custom,
+        ..Default::default()
+      },
+    )
+    .matches(false, false, false);
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(results.first().unwrap().pattern, "ip");
+  }
+
+  #[test]
+  fn wrap_url() {
+    let lines = split("See https://foo.bar/\nlorem/ipsum 127.0.0.1");
+    let results = new_state(
+      &lines,
+      "abcd",
+      Options {
+        wrapped: &["url"],
+        width: Some(20),
+        ..Default::default()
+      },
+    )
+    .matches(false, false, false);
+
+    assert_eq!(results.len(), 2);
+    assert_eq!(results.first().unwrap().text, "https://foo.bar/lorem/ipsum");
+    assert_eq!(
+      results.first().unwrap().spans,
+      [
+        Span {
+          x: 4,
+          y: 0,
+          text: "https://foo.bar/"
+        },
+        Span {
+          x: 0,
+          y: 1,
+          text: "lorem/ipsum"
+        }
+      ]
+    );
+    assert_eq!(results.last().unwrap().text, "127.0.0.1");
+  }
+
+  #[test]
+  fn wrap_several_lines() {
+    let lines = split("x https://a\nbcdefghijkl\nmn op");
+    let results = new_state(
+      &lines,
+      "abcd",
+      Options {
+        wrapped: &["url"],
+        width: Some(11),
+        ..Default::default()
+      },
+    )
+    .matches(false, false, false);
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(results.first().unwrap().text, "https://abcdefghijklmn");
+    assert_eq!(results.first().unwrap().spans.len(), 3);
+  }
+
+  #[test]
+  fn wrap_copy_group() {
+    let lines = split("Ticket JIRA-12\n34 done");
+    let custom = [Pattern::parse("name:jira=JIRA-(?P<copy>[0-9]+)")].to_vec();
+    let results = new_state(
+      &lines,
+      "abcd",
+      Options {
+        custom: &custom,
+        wrapped: &["jira"],
+        width: Some(14),
+        ..Default::default()
+      },
+    )
+    .matches(false, false, false);
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(results.first().unwrap().text, "1234");
+    assert_eq!(results.first().unwrap().copy, "1234");
+    assert_eq!(results.first().unwrap().spans.len(), 2);
+  }
+
+  #[test]
+  fn wrap_disabled_pattern() {
+    let lines = split("See https://foo.bar/\nlorem/ipsum 127.0.0.1");
+    let results = new_state(
+      &lines,
+      "abcd",
+      Options {
+        width: Some(20),
+        ..Default::default()
+      },
+    )
+    .matches(false, false, false);
+
+    assert_eq!(results.len(), 3);
+    assert_eq!(results.first().unwrap().text, "https://foo.bar/");
+    assert_eq!(results.get(1).unwrap().text, "lorem/ipsum");
+  }
+
+  #[test]
+  fn wrap_short_line() {
+    let lines = split("See https://foo.bar/\nlorem/ipsum 127.0.0.1");
+    let results = new_state(
+      &lines,
+      "abcd",
+      Options {
+        wrapped: &["url"],
+        width: Some(30),
+        ..Default::default()
+      },
+    )
+    .matches(false, false, false);
+
+    assert_eq!(results.len(), 3);
+    assert_eq!(results.first().unwrap().text, "https://foo.bar/");
   }
 }
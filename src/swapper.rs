@@ -1,14 +1,20 @@
 extern crate clap;
 
+// Only the pattern actions are used by tmux-thumbs
+#[allow(dead_code)]
+mod patterns;
+
 use self::clap::{App, Arg};
 use clap::crate_version;
 use regex::Regex;
+use std::collections::HashMap;
 use std::io::Write;
 use std::process::Command;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 trait Executor {
   fn execute(&mut self, args: Vec<String>) -> String;
+  #[allow(dead_code)]
   fn last_executed(&self) -> Option<Vec<String>>;
 }
 
@@ -42,12 +48,13 @@ impl Executor for RealShell {
 }
 
 const TMP_FILE: &str = "/tmp/thumbs-last";
+const HINTS_FILE: &str = "/tmp/thumbs-hints";
+const ERROR_FILE: &str = "/tmp/thumbs-error";
 
 #[allow(dead_code)]
 fn dbg(msg: &str) {
   let mut file = std::fs::OpenOptions::new()
     .create(true)
-    .write(true)
     .append(true)
     .open("/tmp/thumbs.log")
     .expect("Unable to open log file");
@@ -56,30 +63,34 @@ fn dbg(msg: &str) {
 }
 
 pub struct Swapper<'a> {
-  executor: Box<&'a mut dyn Executor>,
+  executor: &'a mut dyn Executor,
   dir: String,
   command: String,
-  upcase_command: String,
+  action_commands: HashMap<String, String>,
   multi_command: String,
+  pattern_commands: HashMap<String, String>,
   osc52: bool,
   active_pane_id: Option<String>,
   active_pane_height: Option<i32>,
+  active_pane_width: Option<i32>,
   active_pane_scroll_position: Option<i32>,
   active_pane_zoomed: Option<bool>,
   thumbs_pane_id: Option<String>,
   content: Option<String>,
+  error: Option<String>,
   signal: String,
 }
 
 impl<'a> Swapper<'a> {
   fn new(
-    executor: Box<&'a mut dyn Executor>,
+    executor: &'a mut dyn Executor,
     dir: String,
     command: String,
-    upcase_command: String,
+    action_commands: HashMap<String, String>,
     multi_command: String,
+    pattern_commands: HashMap<String, String>,
     osc52: bool,
-  ) -> Swapper {
+  ) -> Swapper<'a> {
     let since_the_epoch = SystemTime::now()
       .duration_since(UNIX_EPOCH)
       .expect("Time went backwards");
@@ -89,25 +100,28 @@ impl<'a> Swapper<'a> {
       executor,
       dir,
       command,
-      upcase_command,
+      action_commands,
       multi_command,
+      pattern_commands,
       osc52,
       active_pane_id: None,
       active_pane_height: None,
+      active_pane_width: None,
       active_pane_scroll_position: None,
       active_pane_zoomed: None,
       thumbs_pane_id: None,
       content: None,
+      error: None,
       signal,
     }
   }
 
   pub fn capture_active_pane(&mut self) {
-    let active_command = vec![
+    let active_command = [
       "tmux",
       "list-panes",
       "-F",
-      "#{pane_id}:#{?pane_in_mode,1,0}:#{pane_height}:#{scroll_position}:#{window_zoomed_flag}:#{?pane_active,active,nope}",
+      "#{pane_id}:#{?pane_in_mode,1,0}:#{pane_height}:#{scroll_position}:#{window_zoomed_flag}:#{?pane_active,active,nope}:#{pane_width}",
     ];
 
     let output = self
@@ -122,7 +136,7 @@ impl<'a> Swapper<'a> {
       .find(|&chunks| *chunks.get(5).unwrap() == "active")
       .expect("Unable to find active pane");
 
-    let pane_id = active_pane.get(0).unwrap();
+    let pane_id = active_pane.first().unwrap();
 
     self.active_pane_id = Some(pane_id.to_string());
 
@@ -134,7 +148,7 @@ impl<'a> Swapper<'a> {
 
     self.active_pane_height = Some(pane_height);
 
-    if active_pane.get(1).unwrap().to_string() == "1" {
+    if *active_pane.get(1).unwrap() == "1" {
       let pane_scroll_position = active_pane
         .get(3)
         .unwrap()
@@ -146,11 +160,13 @@ impl<'a> Swapper<'a> {
 
     let zoomed_pane = *active_pane.get(4).expect("Unable to retrieve zoom pane property") == "1";
 
+    self.active_pane_width = active_pane.get(6).and_then(|width| width.parse().ok());
+
     self.active_pane_zoomed = Some(zoomed_pane);
   }
 
   pub fn execute_thumbs(&mut self) {
-    let options_command = vec!["tmux", "show", "-g"];
+    let options_command = ["tmux", "show", "-g"];
     let params: Vec<String> = options_command.iter().map(|arg| arg.to_string()).collect();
     let options = self.executor.execute(params);
     let lines: Vec<&str> = options.split('\n').collect();
@@ -164,14 +180,32 @@ impl<'a> Swapper<'a> {
           let name = captures.get(1).unwrap().as_str();
           let value = captures.get(2).unwrap().as_str();
 
-          let boolean_params = vec!["reverse", "unique", "contrast"];
+          let boolean_params = [
+            "reverse",
+            "unique",
+            "fixed-length",
+            "contrast",
+            "keep-colors",
+            "style-from-tmux",
+            "status",
+          ];
 
-          if boolean_params.iter().any(|&x| x == name) {
+          if boolean_params.contains(&name) {
             return vec![format!("--{}", name)];
           }
 
+          let action_params = ["shift-action", "alt-action", "ctrl-action", "leader-action"];
+
+          if action_params.contains(&name) {
+            let modifier = name.trim_end_matches("-action");
+
+            return vec!["--action".to_string(), format!("'{}={}'", modifier, value)];
+          }
+
           let string_params = vec![
             "alphabet",
+            "leader-key",
+            "navigation-key",
             "position",
             "fg-color",
             "bg-color",
@@ -181,12 +215,34 @@ impl<'a> Swapper<'a> {
             "select-bg-color",
             "multi-fg-color",
             "multi-bg-color",
+            "patterns-file",
           ];
 
-          if string_params.iter().any(|&x| x == name) {
+          if string_params.contains(&name) {
             return vec![format!("--{}", name), format!("'{}'", value)];
           }
 
+          // Colors of the matches of a pattern, like @thumbs-pattern-fg-color-url
+          let pattern_color_params = ["pattern-fg-color", "pattern-bg-color"];
+
+          for param in pattern_color_params.iter() {
+            if let Some(pattern) = name.strip_prefix(param).and_then(|rest| rest.strip_prefix('-')) {
+              return vec![format!("--{}", param), format!("'{}={}'", pattern, value)];
+            }
+          }
+
+          let list_params = ["disable-patterns", "only-patterns", "wrap-patterns"];
+
+          if list_params.contains(&name) {
+            let patterns = value
+              .split(|c: char| c == ',' || c.is_whitespace())
+              .filter(|pattern| !pattern.is_empty())
+              .collect::<Vec<&str>>()
+              .join(",");
+
+            return vec![format!("--{}", name.trim_end_matches('s')), format!("'{}'", patterns)];
+          }
+
           if name.starts_with("regexp") {
             return vec!["--regexp".to_string(), format!("'{}'", value.replace("\\\\", "\\"))];
           }
@@ -200,6 +256,27 @@ impl<'a> Swapper<'a> {
 
     let active_pane_id = self.active_pane_id.as_mut().unwrap().clone();
 
+    // Hyperlinks and colors are only captured along with the rest of escape sequences
+    let escape_params = if lines
+      .iter()
+      .any(|line| line.starts_with("@thumbs-hyperlinks ") || line.starts_with("@thumbs-keep-colors "))
+    {
+      " -e"
+    } else {
+      ""
+    };
+
+    // Each pane remembers its own hints
+    let hints_params = if lines.iter().any(|line| line.starts_with("@thumbs-stable-hints ")) {
+      format!(
+        " --hints-file {}-{}",
+        HINTS_FILE,
+        active_pane_id.trim_start_matches('%')
+      )
+    } else {
+      "".to_string()
+    };
+
     let scroll_params =
       if let (Some(pane_height), Some(scroll_position)) = (self.active_pane_height, self.active_pane_scroll_position) {
         format!(" -S {} -E {}", -scroll_position, pane_height - scroll_position - 1)
@@ -207,7 +284,13 @@ impl<'a> Swapper<'a> {
         "".to_string()
       };
 
-    let active_pane_zoomed = self.active_pane_zoomed.as_mut().unwrap().clone();
+    let width_params = if let Some(pane_width) = self.active_pane_width {
+      format!(" --width {}", pane_width)
+    } else {
+      "".to_string()
+    };
+
+    let active_pane_zoomed = self.active_pane_zoomed.unwrap();
     let zoom_command = if active_pane_zoomed {
       format!("tmux resize-pane -t {} -Z;", active_pane_id)
     } else {
@@ -215,12 +298,16 @@ impl<'a> Swapper<'a> {
     };
 
     let pane_command = format!(
-        "tmux capture-pane -J -t {active_pane_id} -p{scroll_params} | tail -n {height} | {dir}/target/release/thumbs -f '%U:%H' -t {tmp} {args}; tmux swap-pane -t {active_pane_id}; {zoom_command} tmux wait-for -S {signal}",
+        "tmux capture-pane -J -t {active_pane_id} -p{escape_params}{scroll_params} | tail -n {height} | {dir}/target/release/thumbs -f '%A:%P:%H' -t {tmp}{width_params}{hints_params} {args} 2> {error}; tmux swap-pane -t {active_pane_id}; {zoom_command} tmux wait-for -S {signal}",
         active_pane_id = active_pane_id,
+        escape_params = escape_params,
         scroll_params = scroll_params,
         height = self.active_pane_height.unwrap_or(i32::MAX),
         dir = self.dir,
         tmp = TMP_FILE,
+        error = ERROR_FILE,
+        width_params = width_params,
+        hints_params = hints_params,
         args = args.join(" "),
         zoom_command = zoom_command,
         signal = self.signal
@@ -247,7 +334,7 @@ impl<'a> Swapper<'a> {
     let active_pane_id = self.active_pane_id.as_mut().unwrap().clone();
     let thumbs_pane_id = self.thumbs_pane_id.as_mut().unwrap().clone();
 
-    let swap_command = vec![
+    let swap_command = [
       "tmux",
       "swap-pane",
       "-d",
@@ -267,7 +354,7 @@ impl<'a> Swapper<'a> {
   }
 
   pub fn resize_pane(&mut self) {
-    let active_pane_zoomed = self.active_pane_zoomed.as_mut().unwrap().clone();
+    let active_pane_zoomed = self.active_pane_zoomed.unwrap();
 
     if !active_pane_zoomed {
       return;
@@ -275,7 +362,7 @@ impl<'a> Swapper<'a> {
 
     let thumbs_pane_id = self.thumbs_pane_id.as_mut().unwrap().clone();
 
-    let resize_command = vec!["tmux", "resize-pane", "-t", thumbs_pane_id.as_str(), "-Z"];
+    let resize_command = ["tmux", "resize-pane", "-t", thumbs_pane_id.as_str(), "-Z"];
 
     let params = resize_command
       .iter()
@@ -287,36 +374,68 @@ impl<'a> Swapper<'a> {
   }
 
   pub fn wait_thumbs(&mut self) {
-    let wait_command = vec!["tmux", "wait-for", self.signal.as_str()];
+    let wait_command = ["tmux", "wait-for", self.signal.as_str()];
     let params = wait_command.iter().map(|arg| arg.to_string()).collect();
 
     self.executor.execute(params);
   }
 
   pub fn retrieve_content(&mut self) {
-    let retrieve_command = vec!["cat", TMP_FILE];
+    let retrieve_command = ["cat", TMP_FILE];
     let params = retrieve_command.iter().map(|arg| arg.to_string()).collect();
 
     self.content = Some(self.executor.execute(params));
   }
 
   pub fn destroy_content(&mut self) {
-    let retrieve_command = vec!["rm", TMP_FILE];
+    let retrieve_command = ["rm", TMP_FILE];
     let params = retrieve_command.iter().map(|arg| arg.to_string()).collect();
 
     self.executor.execute(params);
   }
 
+  /// Errors of thumbs, like an invalid option, to show them instead of running any command
+  pub fn retrieve_error(&mut self) {
+    let retrieve_command = ["cat", ERROR_FILE];
+    let params = retrieve_command.iter().map(|arg| arg.to_string()).collect();
+
+    self.error = Some(self.executor.execute(params)).filter(|error| !error.is_empty());
+
+    let destroy_command = ["rm", "-f", ERROR_FILE];
+    let params = destroy_command.iter().map(|arg| arg.to_string()).collect();
+
+    self.executor.execute(params);
+  }
+
   pub fn send_osc52(&mut self) {}
 
   pub fn execute_command(&mut self) {
+    if let Some(error) = self.error.clone() {
+      // Errors like the ones of regexps span several lines, and the last ones tell the cause. The lines
+      // with only a caret point to a column that a single line message loses. A '#' would start a tmux
+      // format.
+      let message = error
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.chars().all(|c| c == '^'))
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace('#', "##");
+      let display_command = ["tmux", "display-message", message.as_str()];
+      let params = display_command.iter().map(|arg| arg.to_string()).collect();
+
+      self.executor.execute(params);
+
+      return;
+    }
+
     let content = self.content.clone().unwrap();
     let items: Vec<&str> = content.split('\n').collect();
 
     if items.len() > 1 {
       let text = items
         .iter()
-        .map(|item| item.splitn(2, ':').last().unwrap())
+        .map(|item| item.splitn(3, ':').last().unwrap())
         .collect::<Vec<&str>>()
         .join(" ");
 
@@ -328,9 +447,9 @@ impl<'a> Swapper<'a> {
     // Only one item
     let item: &str = items.first().unwrap();
 
-    let mut splitter = item.splitn(2, ':');
+    let mut splitter = item.splitn(3, ':');
 
-    if let Some(upcase) = splitter.next() {
+    if let (Some(action), Some(pattern)) = (splitter.next(), splitter.next()) {
       if let Some(text) = splitter.next() {
         if self.osc52 {
           let base64_text = base64::encode(text.as_bytes());
@@ -360,8 +479,11 @@ impl<'a> Swapper<'a> {
           std::io::stdout().flush().unwrap();
         }
 
-        let execute_command = if upcase.trim_end() == "true" {
-          self.upcase_command.clone()
+        // Hints picked with a modifier run the command of its action
+        let execute_command = if let Some(action_command) = self.action_commands.get(action) {
+          action_command.clone()
+        } else if let Some(pattern_command) = self.pattern_commands.get(pattern) {
+          pattern_command.clone()
         } else {
           self.command.clone()
         };
@@ -396,7 +518,7 @@ impl<'a> Swapper<'a> {
 
   pub fn execute_final_command(&mut self, text: &str, execute_command: &str) {
     let final_command = str::replace(execute_command, "{}", "${THUMB}");
-    let retrieve_command = vec![
+    let retrieve_command = [
       "bash",
       "-c",
       "THUMB=\"$1\"; eval \"$2\"",
@@ -411,6 +533,135 @@ impl<'a> Swapper<'a> {
   }
 }
 
+fn app_args<'a>() -> clap::ArgMatches<'a> {
+  App::new("tmux-thumbs")
+    .version(crate_version!())
+    .about("A lightning fast version of tmux-fingers, copy/pasting tmux like vimium/vimperator")
+    .arg(
+      Arg::with_name("dir")
+        .help("Directory where to execute thumbs")
+        .long("dir")
+        .default_value(""),
+    )
+    .arg(
+      Arg::with_name("command")
+        .help("Command to execute after choose a hint")
+        .long("command")
+        .default_value("tmux set-buffer -- \"{}\" && tmux display-message \"Copied {}\""),
+    )
+    .arg(
+      Arg::with_name("upcase_command")
+        .help("Command to execute after choose a hint, in upcase")
+        .long("upcase-command")
+        .default_value("tmux set-buffer -- \"{}\" && tmux paste-buffer && tmux display-message \"Copied {}\""),
+    )
+    .arg(
+      Arg::with_name("action_command")
+        .help("Command to execute after choose a hint for an action, as name=COMMAND")
+        .long("action-command")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1),
+    )
+    .arg(
+      Arg::with_name("multi_command")
+        .help("Command to execute after choose multiple hints")
+        .long("multi-command")
+        .default_value("tmux set-buffer -- \"{}\" && tmux paste-buffer && tmux display-message \"Multi copied {}\""),
+    )
+    .arg(
+      Arg::with_name("pattern_command")
+        .help("Command to execute after choose a hint of a pattern, as name=COMMAND")
+        .long("pattern-command")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1),
+    )
+    .arg(
+      Arg::with_name("patterns_file")
+        .help("Load pattern actions from this TOML file")
+        .long("patterns-file")
+        .takes_value(true),
+    )
+    .arg(
+      Arg::with_name("osc52")
+        .help("Print OSC52 copy escape sequence in addition to running the pick command")
+        .long("osc52")
+        .short("o"),
+    )
+    .get_matches()
+}
+
+fn main() -> std::io::Result<()> {
+  let args = app_args();
+  let dir = args.value_of("dir").unwrap();
+  let command = args.value_of("command").unwrap();
+  let upcase_command = args.value_of("upcase_command").unwrap();
+  let multi_command = args.value_of("multi_command").unwrap();
+  let osc52 = args.is_present("osc52");
+  let mut pattern_commands = HashMap::new();
+  let mut action_commands = HashMap::new();
+
+  action_commands.insert("upcase".to_string(), upcase_command.to_string());
+
+  if let Some(items) = args.values_of("action_command") {
+    for item in items {
+      let mut splitter = item.splitn(2, '=');
+
+      if let (Some(name), Some(command)) = (splitter.next(), splitter.next()) {
+        action_commands.insert(name.to_string(), command.to_string());
+      }
+    }
+  }
+
+  // Actions from the patterns file can be overridden with @thumbs-command-NAME
+  if let Some(path) = args.value_of("patterns_file") {
+    // thumbs reports an invalid patterns file by itself
+    for definition in patterns::load(path).map(|file| file.pattern).unwrap_or_default() {
+      if let Some(action) = definition.action {
+        pattern_commands.insert(definition.name, action);
+      }
+    }
+  }
+
+  if let Some(items) = args.values_of("pattern_command") {
+    for item in items {
+      let mut splitter = item.splitn(2, '=');
+
+      if let (Some(name), Some(command)) = (splitter.next(), splitter.next()) {
+        pattern_commands.insert(name.to_string(), command.to_string());
+      }
+    }
+  }
+
+  if dir.is_empty() {
+    panic!("Invalid tmux-thumbs execution. Are you trying to execute tmux-thumbs directly?")
+  }
+
+  let mut executor = RealShell::new();
+  let mut swapper = Swapper::new(
+    &mut executor,
+    dir.to_string(),
+    command.to_string(),
+    action_commands,
+    multi_command.to_string(),
+    pattern_commands,
+    osc52,
+  );
+
+  swapper.capture_active_pane();
+  swapper.execute_thumbs();
+  swapper.swap_panes();
+  swapper.resize_pane();
+  swapper.wait_thumbs();
+  swapper.retrieve_content();
+  swapper.destroy_content();
+  swapper.retrieve_error();
+  swapper.execute_command();
+
+  Ok(())
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -440,16 +691,42 @@ mod tests {
     }
   }
 
+  /// Command running thumbs with these tmux options, as `(name, value)` pairs without `@thumbs-`
+  fn thumbs_command(options: &[(&str, &str)]) -> String {
+    let options = options
+      .iter()
+      .map(|(name, value)| format!("@thumbs-{} \"{}\"", name, value))
+      .collect::<Vec<_>>()
+      .join("\n");
+    let last_command_outputs = vec!["%100".to_string(), options, "%98:100:24:1:0:active:80".to_string()];
+    let mut executor = TestShell::new(last_command_outputs);
+    let mut swapper = Swapper::new(
+      &mut executor,
+      "".to_string(),
+      "".to_string(),
+      HashMap::new(),
+      "".to_string(),
+      HashMap::new(),
+      false,
+    );
+
+    swapper.capture_active_pane();
+    swapper.execute_thumbs();
+
+    executor.last_executed().unwrap().last().unwrap().clone()
+  }
+
   #[test]
   fn retrieve_active_pane() {
     let last_command_outputs = vec!["%97:100:24:1:0:active\n%106:100:24:1:0:nope\n%107:100:24:1:0:nope\n".to_string()];
     let mut executor = TestShell::new(last_command_outputs);
     let mut swapper = Swapper::new(
-      Box::new(&mut executor),
-      "".to_string(),
+      &mut executor,
       "".to_string(),
       "".to_string(),
+      HashMap::new(),
       "".to_string(),
+      HashMap::new(),
       false,
     );
 
@@ -468,11 +745,12 @@ mod tests {
     ];
     let mut executor = TestShell::new(last_command_outputs);
     let mut swapper = Swapper::new(
-      Box::new(&mut executor),
-      "".to_string(),
+      &mut executor,
       "".to_string(),
       "".to_string(),
+      HashMap::new(),
       "".to_string(),
+      HashMap::new(),
       false,
     );
 
@@ -491,20 +769,23 @@ mod tests {
     let mut executor = TestShell::new(last_command_outputs);
 
     let user_command = "echo \"{}\"".to_string();
-    let upcase_command = "open \"{}\"".to_string();
+    let mut action_commands = HashMap::new();
+    action_commands.insert("upcase".to_string(), "open \"{}\"".to_string());
     let multi_command = "open \"{}\"".to_string();
     let mut swapper = Swapper::new(
-      Box::new(&mut executor),
+      &mut executor,
       "".to_string(),
       user_command,
-      upcase_command,
+      action_commands,
       multi_command,
+      HashMap::new(),
       false,
     );
 
     swapper.content = Some(format!(
-      "{do_upcase}:{thumb_text}",
-      do_upcase = false,
+      "{action}:{pattern}:{thumb_text}",
+      action = "",
+      pattern = "custom",
       thumb_text = "foobar;rm *",
     ));
     swapper.execute_command();
@@ -526,75 +807,221 @@ mod tests {
 
     assert_eq!(executor.last_executed().unwrap(), expectation);
   }
-}
 
-fn app_args<'a>() -> clap::ArgMatches<'a> {
-  App::new("tmux-thumbs")
-    .version(crate_version!())
-    .about("A lightning fast version of tmux-fingers, copy/pasting tmux like vimium/vimperator")
-    .arg(
-      Arg::with_name("dir")
-        .help("Directory where to execute thumbs")
-        .long("dir")
-        .default_value(""),
-    )
-    .arg(
-      Arg::with_name("command")
-        .help("Command to execute after choose a hint")
-        .long("command")
-        .default_value("tmux set-buffer -- \"{}\" && tmux display-message \"Copied {}\""),
-    )
-    .arg(
-      Arg::with_name("upcase_command")
-        .help("Command to execute after choose a hint, in upcase")
-        .long("upcase-command")
-        .default_value("tmux set-buffer -- \"{}\" && tmux paste-buffer && tmux display-message \"Copied {}\""),
-    )
-    .arg(
-      Arg::with_name("multi_command")
-        .help("Command to execute after choose multiple hints")
-        .long("multi-command")
-        .default_value("tmux set-buffer -- \"{}\" && tmux paste-buffer && tmux display-message \"Multi copied {}\""),
-    )
-    .arg(
-      Arg::with_name("osc52")
-        .help("Print OSC52 copy escape sequence in addition to running the pick command")
-        .long("osc52")
-        .short("o"),
-    )
-    .get_matches()
-}
+  #[test]
+  fn pattern_execution() {
+    let last_command_outputs = vec!["".to_string()];
+    let mut executor = TestShell::new(last_command_outputs);
 
-fn main() -> std::io::Result<()> {
-  let args = app_args();
-  let dir = args.value_of("dir").unwrap();
-  let command = args.value_of("command").unwrap();
-  let upcase_command = args.value_of("upcase_command").unwrap();
-  let multi_command = args.value_of("multi_command").unwrap();
-  let osc52 = args.is_present("osc52");
+    let mut pattern_commands = HashMap::new();
+    pattern_commands.insert("sha".to_string(), "git show {}".to_string());
 
-  if dir.is_empty() {
-    panic!("Invalid tmux-thumbs execution. Are you trying to execute tmux-thumbs directly?")
+    let mut swapper = Swapper::new(
+      &mut executor,
+      "".to_string(),
+      "echo {}".to_string(),
+      HashMap::new(),
+      "open {}".to_string(),
+      pattern_commands,
+      false,
+    );
+
+    swapper.content = Some(":sha:f924213".to_string());
+    swapper.execute_command();
+
+    let expectation = vec![
+      "bash",
+      "-c",
+      "THUMB=\"$1\"; eval \"$2\"",
+      "--",
+      "f924213",
+      "git show ${THUMB}",
+    ];
+
+    assert_eq!(executor.last_executed().unwrap(), expectation);
   }
 
-  let mut executor = RealShell::new();
-  let mut swapper = Swapper::new(
-    Box::new(&mut executor),
-    dir.to_string(),
-    command.to_string(),
-    upcase_command.to_string(),
-    multi_command.to_string(),
-    osc52,
-  );
+  #[test]
+  fn pattern_upcase_execution() {
+    let last_command_outputs = vec!["".to_string()];
+    let mut executor = TestShell::new(last_command_outputs);
 
-  swapper.capture_active_pane();
-  swapper.execute_thumbs();
-  swapper.swap_panes();
-  swapper.resize_pane();
-  swapper.wait_thumbs();
-  swapper.retrieve_content();
-  swapper.destroy_content();
-  swapper.execute_command();
+    let mut pattern_commands = HashMap::new();
+    pattern_commands.insert("sha".to_string(), "git show {}".to_string());
 
-  Ok(())
+    let mut action_commands = HashMap::new();
+    action_commands.insert("upcase".to_string(), "open {}".to_string());
+
+    let mut swapper = Swapper::new(
+      &mut executor,
+      "".to_string(),
+      "echo {}".to_string(),
+      action_commands,
+      "open {}".to_string(),
+      pattern_commands,
+      false,
+    );
+
+    swapper.content = Some("upcase:sha:f924213".to_string());
+    swapper.execute_command();
+
+    let expectation = vec![
+      "bash",
+      "-c",
+      "THUMB=\"$1\"; eval \"$2\"",
+      "--",
+      "f924213",
+      "open ${THUMB}",
+    ];
+
+    assert_eq!(executor.last_executed().unwrap(), expectation);
+  }
+
+  #[test]
+  fn pattern_list_options() {
+    let command = thumbs_command(&[("disable-patterns", "number sha"), ("only-patterns", "url,path")]);
+
+    assert!(command.contains("--disable-pattern 'number,sha'"));
+    assert!(command.contains("--only-pattern 'url,path'"));
+  }
+
+  #[test]
+  fn wrap_options() {
+    let command = thumbs_command(&[("wrap-patterns", "url")]);
+
+    assert!(command.contains("--width 80"));
+    assert!(command.contains("--wrap-pattern 'url'"));
+  }
+
+  #[test]
+  fn keep_colors_option() {
+    let command = thumbs_command(&[("keep-colors", "enabled")]);
+
+    assert!(command.contains("tmux capture-pane -J -t %98 -p -e"));
+    assert!(command.contains("--keep-colors"));
+  }
+
+  #[test]
+  fn stable_hints_option() {
+    let command = thumbs_command(&[("stable-hints", "enabled")]);
+
+    assert!(command.contains("--hints-file /tmp/thumbs-hints-98"));
+  }
+
+  #[test]
+  fn action_options() {
+    let command = thumbs_command(&[("alt-action", "open"), ("leader-action", "edit"), ("leader-key", ",")]);
+
+    assert!(command.contains("-f '%A:%P:%H'"));
+    assert!(command.contains("--action 'alt=open' --action 'leader=edit' --leader-key ','"));
+  }
+
+  #[test]
+  fn pattern_color_options() {
+    let command = thumbs_command(&[
+      ("fg-color", "green"),
+      ("pattern-fg-color-url", "blue"),
+      ("pattern-bg-color-sha", "colour236"),
+    ]);
+
+    assert!(command.contains("--fg-color 'green' --pattern-fg-color 'url=blue' --pattern-bg-color 'sha=colour236'"));
+  }
+
+  #[test]
+  fn action_execution() {
+    let last_command_outputs = vec!["".to_string()];
+    let mut executor = TestShell::new(last_command_outputs);
+
+    let mut action_commands = HashMap::new();
+    action_commands.insert("open".to_string(), "xdg-open {}".to_string());
+
+    let mut swapper = Swapper::new(
+      &mut executor,
+      "".to_string(),
+      "echo {}".to_string(),
+      action_commands,
+      "".to_string(),
+      HashMap::new(),
+      false,
+    );
+
+    swapper.content = Some("open:url:https://foo.bar".to_string());
+    swapper.execute_command();
+
+    let expectation = vec![
+      "bash",
+      "-c",
+      "THUMB=\"$1\"; eval \"$2\"",
+      "--",
+      "https://foo.bar",
+      "xdg-open ${THUMB}",
+    ];
+
+    assert_eq!(executor.last_executed().unwrap(), expectation);
+  }
+
+  #[test]
+  fn error_display() {
+    let last_command_outputs = vec![
+      "".to_string(),
+      "".to_string(),
+      "error: Unknown color: #wat\nmore details".to_string(),
+    ];
+    let mut executor = TestShell::new(last_command_outputs);
+    let mut swapper = Swapper::new(
+      &mut executor,
+      "".to_string(),
+      "echo {}".to_string(),
+      HashMap::new(),
+      "".to_string(),
+      HashMap::new(),
+      false,
+    );
+
+    swapper.content = Some("".to_string());
+    swapper.retrieve_error();
+    swapper.execute_command();
+
+    let expectation = vec!["tmux", "display-message", "error: Unknown color: ##wat more details"];
+
+    assert_eq!(executor.last_executed().unwrap(), expectation);
+  }
+
+  #[test]
+  fn regexp_error_display() {
+    let last_command_outputs = vec![
+      "".to_string(),
+      "".to_string(),
+      "error: Invalid regexp custom: regex parse error:\n    (lorem\n    ^\nerror: unclosed group\n".to_string(),
+    ];
+    let mut executor = TestShell::new(last_command_outputs);
+    let mut swapper = Swapper::new(
+      &mut executor,
+      "".to_string(),
+      "echo {}".to_string(),
+      HashMap::new(),
+      "".to_string(),
+      HashMap::new(),
+      false,
+    );
+
+    swapper.content = Some("".to_string());
+    swapper.retrieve_error();
+    swapper.execute_command();
+
+    let expectation = vec![
+      "tmux",
+      "display-message",
+      "error: Invalid regexp custom: regex parse error: (lorem error: unclosed group",
+    ];
+
+    assert_eq!(executor.last_executed().unwrap(), expectation);
+  }
+
+  #[test]
+  fn hyperlinks_option() {
+    let command = thumbs_command(&[("hyperlinks", "enabled")]);
+
+    assert!(command.contains("tmux capture-pane -J -t %98 -p -e"));
+  }
 }
@@ -1,31 +1,92 @@
 use super::*;
+use actions::Modifier;
 use std::char;
+use std::collections::HashMap;
 use std::io::{stdout, Read, Write};
 use termion::async_stdin;
 use termion::event::Key;
 use termion::input::TermRead;
 use termion::raw::IntoRawMode;
 use termion::screen::AlternateScreen;
-use termion::{color, cursor};
+use termion::{clear, cursor, style};
 
 use unicode_width::UnicodeWidthStr;
 
+/// A picked match, with the text to copy, its pattern and the action it was picked for
+pub type Chosen<'a> = (String, String, Option<&'a str>);
+
 pub struct View<'a> {
   state: &'a mut state::State<'a>,
   skip: usize,
   multi: bool,
+  reverse: bool,
+  unique: bool,
+  fixed_length: bool,
   contrast: bool,
+  keep_colors: bool,
+  show_status: bool,
   position: &'a str,
+  /// Every match of the patterns, while `matches` are the ones shown
+  found: Vec<state::Match<'a>>,
+  /// Matches of the regexp searched instead of the patterns
+  searched: Option<Vec<state::Match<'a>>>,
   matches: Vec<state::Match<'a>>,
-  select_foreground_color: Box<dyn color::Color>,
-  select_background_color: Box<dyn color::Color>,
-  multi_foreground_color: Box<dyn color::Color>,
-  multi_background_color: Box<dyn color::Color>,
-  foreground_color: Box<dyn color::Color>,
-  background_color: Box<dyn color::Color>,
-  hint_background_color: Box<dyn color::Color>,
-  hint_foreground_color: Box<dyn color::Color>,
-  chosen: Vec<(String, bool)>,
+  filter: String,
+  /// Name of the only pattern shown, picked with Tab
+  pattern: Option<&'a str>,
+  regexp: String,
+  prompt: Option<Prompt>,
+  select_foreground_color: colors::Style,
+  select_background_color: colors::Style,
+  multi_foreground_color: colors::Style,
+  multi_background_color: colors::Style,
+  foreground_color: colors::Style,
+  background_color: colors::Style,
+  hint_background_color: colors::Style,
+  hint_foreground_color: colors::Style,
+  pattern_colors: HashMap<&'a str, colors::Style>,
+  pattern_background_colors: HashMap<&'a str, colors::Style>,
+  actions: &'a [actions::Action<'a>],
+  /// Key switching to the navigation with the keys of vim, and back
+  navigation_key: Option<char>,
+  navigating: bool,
+  chosen: Vec<Chosen<'a>>,
+}
+
+/// How the matches are shown and picked. The default colors of the terminal and no actions by default.
+#[derive(Default)]
+pub struct Options<'a> {
+  pub multi: bool,
+  pub reverse: bool,
+  pub unique: bool,
+  pub fixed_length: bool,
+  pub contrast: bool,
+  pub keep_colors: bool,
+  /// Show the status bar from the start
+  pub show_status: bool,
+  pub position: &'a str,
+  pub select_foreground_color: colors::Style,
+  pub select_background_color: colors::Style,
+  pub multi_foreground_color: colors::Style,
+  pub multi_background_color: colors::Style,
+  pub foreground_color: colors::Style,
+  pub background_color: colors::Style,
+  pub hint_foreground_color: colors::Style,
+  pub hint_background_color: colors::Style,
+  /// Colors of the matches of each pattern, by name
+  pub pattern_colors: HashMap<&'a str, colors::Style>,
+  pub pattern_background_colors: HashMap<&'a str, colors::Style>,
+  pub actions: &'a [actions::Action<'a>],
+  pub navigation_key: Option<char>,
+}
+
+/// What the text typed on the last row is for
+#[derive(Clone, Copy, PartialEq)]
+enum Prompt {
+  /// Text the shown matches contain, typed after `/`
+  Filter,
+  /// A regexp to search instead of the patterns, typed after `:`
+  Regexp,
 }
 
 enum CaptureEvent {
@@ -34,32 +95,51 @@ enum CaptureEvent {
 }
 
 impl<'a> View<'a> {
-  pub fn new(
-    state: &'a mut state::State<'a>,
-    multi: bool,
-    reverse: bool,
-    unique: bool,
-    contrast: bool,
-    position: &'a str,
-    select_foreground_color: Box<dyn color::Color>,
-    select_background_color: Box<dyn color::Color>,
-    multi_foreground_color: Box<dyn color::Color>,
-    multi_background_color: Box<dyn color::Color>,
-    foreground_color: Box<dyn color::Color>,
-    background_color: Box<dyn color::Color>,
-    hint_foreground_color: Box<dyn color::Color>,
-    hint_background_color: Box<dyn color::Color>,
-  ) -> View<'a> {
-    let matches = state.matches(reverse, unique);
-    let skip = if reverse { matches.len() - 1 } else { 0 };
+  pub fn new(state: &'a mut state::State<'a>, options: Options<'a>) -> View<'a> {
+    let Options {
+      multi,
+      reverse,
+      unique,
+      fixed_length,
+      contrast,
+      keep_colors,
+      show_status,
+      position,
+      select_foreground_color,
+      select_background_color,
+      multi_foreground_color,
+      multi_background_color,
+      foreground_color,
+      background_color,
+      hint_foreground_color,
+      hint_background_color,
+      pattern_colors,
+      pattern_background_colors,
+      actions,
+      navigation_key,
+    } = options;
+
+    let matches = state.matches(reverse, unique, fixed_length);
+    let skip = if reverse { matches.len().saturating_sub(1) } else { 0 };
 
     View {
       state,
       skip,
       multi,
+      reverse,
+      unique,
+      fixed_length,
       contrast,
+      keep_colors,
+      show_status,
       position,
+      found: matches.clone(),
+      searched: None,
       matches,
+      filter: String::new(),
+      pattern: None,
+      regexp: String::new(),
+      prompt: None,
       select_foreground_color,
       select_background_color,
       multi_foreground_color,
@@ -68,10 +148,186 @@ impl<'a> View<'a> {
       background_color,
       hint_foreground_color,
       hint_background_color,
+      pattern_colors,
+      pattern_background_colors,
+      actions,
+      navigation_key,
+      navigating: false,
       chosen: vec![],
     }
   }
 
+  /// Name of the action picked with this modifier, if any
+  fn action(&self, modifier: Modifier) -> Option<&'a str> {
+    self
+      .actions
+      .iter()
+      .find(|action| action.modifier == modifier)
+      .map(|action| action.name)
+  }
+
+  /// Hints of the matches on screen, to give them back in the next run
+  pub fn remembered(&self) -> Vec<history::Entry> {
+    self.state.remember(&self.found)
+  }
+
+  /// Narrows the matches to the ones of the pattern containing the filter, with new hints for them.
+  /// Without a filter nor a pattern, every match is back with its first hint.
+  fn apply_filter(&mut self) {
+    let matches = self.searched.as_ref().unwrap_or(&self.found);
+
+    self.matches = if self.filter.is_empty() && self.pattern.is_none() {
+      matches.clone()
+    } else {
+      let matches = matches
+        .iter()
+        .filter(|mat| {
+          self.pattern.map_or(true, |pattern| mat.pattern == pattern) && mat.text.contains(self.filter.as_str())
+        })
+        .map(|mat| state::Match {
+          hint: None,
+          ..mat.clone()
+        })
+        .collect();
+
+      self.state.hint(matches, self.reverse, self.unique, self.fixed_length)
+    };
+
+    self.skip = if self.reverse {
+      self.matches.len().saturating_sub(1)
+    } else {
+      0
+    };
+  }
+
+  /// Shows the matches of the regexp instead of the ones of the patterns, which are back without a
+  /// regexp. Tells if the regexp is valid.
+  fn apply_regexp(&mut self) -> bool {
+    if self.regexp.is_empty() {
+      self.searched = None;
+    } else {
+      match self
+        .state
+        .search(&self.regexp, self.reverse, self.unique, self.fixed_length)
+      {
+        Ok(matches) => self.searched = Some(matches),
+        Err(_) => return false,
+      }
+    }
+
+    self.filter.clear();
+    self.pattern = None;
+    self.apply_filter();
+
+    true
+  }
+
+  /// Shows only the matches of the next pattern found, or of the previous one going backward. All of
+  /// them are back after the last pattern. Patterns without a match containing the filter are skipped.
+  fn cycle_pattern(&mut self, forward: bool) {
+    let mut names: Vec<&'a str> = Vec::new();
+    let matches = self.searched.as_ref().unwrap_or(&self.found);
+
+    for mat in matches.iter().filter(|mat| mat.text.contains(self.filter.as_str())) {
+      if !names.contains(&mat.pattern) {
+        names.push(mat.pattern);
+      }
+    }
+
+    let position = self
+      .pattern
+      .and_then(|pattern| names.iter().position(|name| *name == pattern));
+
+    self.pattern = match (position, forward) {
+      (None, true) => names.first().copied(),
+      (None, false) => names.last().copied(),
+      (Some(position), true) => names.get(position + 1).copied(),
+      (Some(position), false) => position
+        .checked_sub(1)
+        .and_then(|position| names.get(position))
+        .copied(),
+    };
+
+    self.apply_filter();
+  }
+
+  /// Text typed in the prompt
+  fn typed(&mut self, prompt: Prompt) -> &mut String {
+    match prompt {
+      Prompt::Filter => &mut self.filter,
+      Prompt::Regexp => &mut self.regexp,
+    }
+  }
+
+  /// Applies the text typed in the prompt. Tells if it is valid.
+  fn apply(&mut self, prompt: Prompt) -> bool {
+    match prompt {
+      Prompt::Filter => {
+        self.apply_filter();
+        true
+      }
+      Prompt::Regexp => self.apply_regexp(),
+    }
+  }
+
+  /// Size of the pane, as columns and rows. Prompts go on its last row.
+  fn size(&self) -> (u16, u16) {
+    termion::terminal_size().unwrap_or((u16::MAX, self.state.lines.len() as u16))
+  }
+
+  /// The mode, the typed letters, the chosen matches, what narrows the matches and the keys to use
+  fn status_line(&self, typed_hint: &str) -> String {
+    let mut parts = vec![if self.multi { "multi" } else { "single" }.to_string()];
+
+    if self.navigating {
+      parts.push("navigation".to_string());
+    }
+
+    if !typed_hint.is_empty() {
+      parts.push(format!("typed: {}", typed_hint));
+    }
+
+    if self.multi {
+      parts.push(format!("chosen: {}", self.chosen.len()));
+    }
+
+    if let Some(pattern) = self.pattern {
+      parts.push(format!("pattern: {}", pattern));
+    }
+
+    if !self.filter.is_empty() {
+      parts.push(format!("filter: {}", self.filter));
+    }
+
+    if !self.regexp.is_empty() {
+      parts.push(format!("search: {}", self.regexp));
+    }
+
+    // Keys typing letters of the hints do nothing else
+    let mut keys = [
+      ("/", "filter"),
+      (":", "search"),
+      ("Tab", "pattern"),
+      ("Space", if self.multi { "done" } else { "multi" }),
+      ("Enter", "pick"),
+      ("Esc", "back"),
+      ("?", "help"),
+    ]
+    .iter()
+    .filter(|(key, _)| key.len() > 1 || !self.state.is_hint_key(key.chars().next().unwrap()))
+    .map(|(key, help)| format!("{} {}", key, help))
+    .collect::<Vec<_>>();
+
+    match self.navigation_key {
+      Some(_) if self.navigating => keys.insert(0, "hjkl w b gg G move".to_string()),
+      Some(key) => keys.push(format!("{} move", key)),
+      None => {}
+    }
+
+    parts.push(keys.join("  "));
+    parts.join(" | ")
+  }
+
   pub fn prev(&mut self) {
     if self.skip > 0 {
       self.skip -= 1;
@@ -79,11 +335,134 @@ impl<'a> View<'a> {
   }
 
   pub fn next(&mut self) {
-    if self.skip < self.matches.len() - 1 {
+    if self.skip + 1 < self.matches.len() {
       self.skip += 1;
     }
   }
 
+  /// Selects the previous or the next match of the same line, if any
+  fn select_in_line(&mut self, forward: bool) {
+    let line = match self.matches.get(self.skip) {
+      Some(mat) => mat.spans[0].y,
+      None => return,
+    };
+    let index = if forward {
+      self.skip + 1
+    } else {
+      self.skip.wrapping_sub(1)
+    };
+
+    if self.matches.get(index).is_some_and(|mat| mat.spans[0].y == line) {
+      self.skip = index;
+    }
+  }
+
+  /// Selects the match of the nearest line below or above with matches, the closest one to the column of
+  /// the selected match
+  fn select_line(&mut self, down: bool) {
+    let position = |mat: &state::Match| {
+      let span = &mat.spans[0];
+
+      (span.y, self.state.lines[span.y as usize].column(span.x as usize) as i32)
+    };
+
+    let (line, column) = match self.matches.get(self.skip) {
+      Some(mat) => position(mat),
+      None => return,
+    };
+
+    let nearest = self
+      .matches
+      .iter()
+      .map(|mat| mat.spans[0].y)
+      .filter(|y| if down { *y > line } else { *y < line })
+      .min_by_key(|y| (y - line).abs());
+
+    if let Some(nearest) = nearest {
+      let index = (0..self.matches.len())
+        .filter(|index| self.matches[*index].spans[0].y == nearest)
+        .min_by_key(|index| (position(&self.matches[*index]).1 - column).abs());
+
+      if let Some(index) = index {
+        self.skip = index;
+      }
+    }
+  }
+
+  /// A prompt takes every printable letter as typed, until Enter keeps its text or Esc drops it. Filters
+  /// narrow the matches while typed, regexps are only searched on Enter.
+  fn prompt_key(&mut self, prompt: Prompt, key: Key) {
+    match key {
+      Key::Esc => {
+        self.prompt = None;
+        self.typed(prompt).clear();
+        self.apply(prompt);
+      }
+      Key::Char('\n') => {
+        // An invalid regexp stays in the prompt, to be fixed
+        let valid = self.apply(prompt);
+
+        if valid {
+          self.prompt = None;
+        }
+      }
+      Key::Backspace => {
+        if self.typed(prompt).pop().is_none() {
+          self.prompt = None;
+          self.apply(prompt);
+        } else if prompt == Prompt::Filter {
+          self.apply(prompt);
+        }
+      }
+      // Control characters, like Tab, can't be typed in
+      Key::Char(ch) if !ch.is_control() => {
+        self.typed(prompt).push(ch);
+
+        if prompt == Prompt::Filter {
+          self.apply(prompt);
+        }
+      }
+      Key::Up => {
+        self.select_line(false);
+      }
+      Key::Down => {
+        self.select_line(true);
+      }
+      Key::Left => {
+        self.prev();
+      }
+      Key::Right => {
+        self.next();
+      }
+      _ => {}
+    }
+  }
+
+  /// Moves the selection while navigating, like vim: h and l in the line, j and k to the lines below and
+  /// above, w and b to the next and previous matches, gg and G to the first and last ones. Tells if the
+  /// key was taken, as any other letter is, not to type hints.
+  fn navigate(&mut self, key: Key, pending: &mut bool) -> bool {
+    let after_g = std::mem::replace(pending, false);
+
+    match key {
+      Key::Char('h') => self.select_in_line(false),
+      Key::Char('l') => self.select_in_line(true),
+      Key::Char('j') => self.select_line(true),
+      Key::Char('k') => self.select_line(false),
+      Key::Char('w') => self.next(),
+      Key::Char('b') => self.prev(),
+      Key::Char('g') if after_g => self.skip = 0,
+      Key::Char('g') => *pending = true,
+      Key::Char('G') => self.skip = self.matches.len().saturating_sub(1),
+      Key::Char(key) if Some(key) == self.navigation_key => self.navigating = false,
+      Key::Char(key) if key.is_alphanumeric() => {}
+      Key::Esc => self.navigating = false,
+      _ => return false,
+    }
+
+    true
+  }
+
   fn make_hint_text(&self, hint: &str) -> String {
     if self.contrast {
       format!("[{}]", hint)
@@ -92,89 +471,152 @@ impl<'a> View<'a> {
     }
   }
 
-  fn render(&self, stdout: &mut dyn Write, typed_hint: &str) -> () {
+  /// Column of a hint relative to the span it goes on. On the right, a hint longer than the span, like the
+  /// short tail of a wrapped match, starts with it.
+  fn hint_position(&self, text: &str, hint: &str) -> i16 {
+    let (text, hint) = (text.width_cjk() as i16, hint.len() as i16);
+
+    match self.position {
+      "right" => std::cmp::max(text - hint, 0),
+      "off_left" => -hint - if self.contrast { 2 } else { 0 },
+      "off_right" => text,
+      _ => 0,
+    }
+  }
+
+  fn render(&self, stdout: &mut dyn Write, typed_hint: &str) {
     write!(stdout, "{}", cursor::Hide).unwrap();
 
+    // The prompt covers the last row, which gets its text back below once closed
+    let (columns, bottom) = self.size();
+    print!("{}{}", cursor::Goto(1, bottom), clear::CurrentLine);
+
     for (index, line) in self.state.lines.iter().enumerate() {
-      let clean = line.trim_end_matches(|c: char| c.is_whitespace());
+      let clean = line.text.trim_end_matches(|c: char| c.is_whitespace());
 
-      if !clean.is_empty() {
-        print!("{goto}{text}", goto = cursor::Goto(1, index as u16 + 1), text = line);
+      if clean.is_empty() {
+        continue;
+      }
+
+      // The colors of the pane are reset at the end of each line, not to leak into the hints
+      if self.keep_colors {
+        print!(
+          "{goto}{text}{reset}",
+          goto = cursor::Goto(1, index as u16 + 1),
+          text = line.styled(),
+          reset = style::Reset
+        );
+      } else {
+        print!(
+          "{goto}{text}",
+          goto = cursor::Goto(1, index as u16 + 1),
+          text = line.text
+        );
       }
     }
 
     let selected = self.matches.get(self.skip);
 
     for mat in self.matches.iter() {
-      let chosen_hint = self.chosen.iter().any(|(hint, _)| hint == mat.text);
+      let chosen_hint = self.chosen.iter().any(|(hint, _, _)| *hint == mat.copy);
 
       let selected_color = if chosen_hint {
         &self.multi_foreground_color
       } else if selected == Some(mat) {
         &self.select_foreground_color
       } else {
-        &self.foreground_color
+        self.pattern_colors.get(mat.pattern).unwrap_or(&self.foreground_color)
       };
       let selected_background_color = if chosen_hint {
         &self.multi_background_color
       } else if selected == Some(mat) {
         &self.select_background_color
       } else {
-        &self.background_color
+        self
+          .pattern_background_colors
+          .get(mat.pattern)
+          .unwrap_or(&self.background_color)
       };
 
-      // Find long utf sequences and extract it from mat.x
-      let line = &self.state.lines[mat.y as usize];
-      let prefix = &line[0..mat.x as usize];
-      let extra = prefix.width_cjk() - prefix.chars().count();
-      let offset = (mat.x as u16) - (extra as u16);
-      let text = self.make_hint_text(mat.text);
+      // The hint goes on the last line of a wrapped match when placed on the right
+      let last = mat.spans.len() - 1;
+      let hint_span = match self.position {
+        "right" | "off_right" => last,
+        _ => 0,
+      };
 
-      print!(
-        "{goto}{background}{foregroud}{text}{resetf}{resetb}",
-        goto = cursor::Goto(offset + 1, mat.y as u16 + 1),
-        foregroud = color::Fg(&**selected_color),
-        background = color::Bg(&**selected_background_color),
-        resetf = color::Fg(color::Reset),
-        resetb = color::Bg(color::Reset),
-        text = &text
-      );
+      for (position, span) in mat.spans.iter().enumerate() {
+        let offset = self.state.lines[span.y as usize].column(span.x as usize) as u16;
+        let text = if mat.spans.len() == 1 {
+          self.make_hint_text(span.text)
+        } else {
+          let opening = if self.contrast && position == 0 { "[" } else { "" };
+          let closing = if self.contrast && position == last { "]" } else { "" };
 
-      if let Some(ref hint) = mat.hint {
-        let extra_position = match self.position {
-          "right" => text.width_cjk() - hint.len(),
-          "off_left" => 0 - hint.len() - if self.contrast { 2 } else { 0 },
-          "off_right" => text.width_cjk(),
-          _ => 0,
+          format!("{}{}{}", opening, span.text, closing)
         };
 
-        let text = self.make_hint_text(hint.as_str());
-        let final_position = std::cmp::max(offset as i16 + extra_position as i16, 0);
-
         print!(
-          "{goto}{background}{foregroud}{text}{resetf}{resetb}",
-          goto = cursor::Goto(final_position as u16 + 1, mat.y as u16 + 1),
-          foregroud = color::Fg(&*self.hint_foreground_color),
-          background = color::Bg(&*self.hint_background_color),
-          resetf = color::Fg(color::Reset),
-          resetb = color::Bg(color::Reset),
+          "{goto}{background}{foregroud}{text}{reset}",
+          goto = cursor::Goto(offset + 1, span.y as u16 + 1),
+          foregroud = selected_color.fg(),
+          background = selected_background_color.bg(),
+          reset = style::Reset,
           text = &text
         );
 
-        if hint.starts_with(typed_hint) {
+        if position != hint_span {
+          continue;
+        }
+
+        if let Some(ref hint) = mat.hint {
+          let extra_position = self.hint_position(&text, hint);
+
+          let text = self.make_hint_text(hint.as_str());
+          let final_position = std::cmp::max(offset as i16 + extra_position, 0);
+
           print!(
-            "{goto}{background}{foregroud}{text}{resetf}{resetb}",
-            goto = cursor::Goto(final_position as u16 + 1, mat.y as u16 + 1),
-            foregroud = color::Fg(&*self.multi_foreground_color),
-            background = color::Bg(&*self.multi_background_color),
-            resetf = color::Fg(color::Reset),
-            resetb = color::Bg(color::Reset),
-            text = &typed_hint
+            "{goto}{background}{foregroud}{text}{reset}",
+            goto = cursor::Goto(final_position as u16 + 1, span.y as u16 + 1),
+            foregroud = self.hint_foreground_color.fg(),
+            background = self.hint_background_color.bg(),
+            reset = style::Reset,
+            text = &text
           );
+
+          if hint.starts_with(typed_hint) {
+            print!(
+              "{goto}{background}{foregroud}{text}{reset}",
+              goto = cursor::Goto(final_position as u16 + 1, span.y as u16 + 1),
+              foregroud = self.multi_foreground_color.fg(),
+              background = self.multi_background_color.bg(),
+              reset = style::Reset,
+              text = &typed_hint
+            );
+          }
         }
       }
     }
 
+    let bar = match self.prompt {
+      Some(Prompt::Filter) => Some(format!("/{}", self.filter)),
+      Some(Prompt::Regexp) => Some(format!(":{}", self.regexp)),
+      None if self.show_status => Some(self.status_line(typed_hint)),
+      None => self.pattern.map(|pattern| format!("pattern: {}", pattern)),
+    };
+
+    // Longer bars would wrap and scroll the pane
+    if let Some(bar) = bar {
+      print!(
+        "{goto}{background}{foregroud}{bar}{reset}",
+        goto = cursor::Goto(1, bottom),
+        foregroud = self.hint_foreground_color.fg(),
+        background = self.hint_background_color.bg(),
+        reset = style::Reset,
+        bar = bar.chars().take(columns as usize).collect::<String>()
+      );
+    }
+
     stdout.flush().unwrap();
   }
 
@@ -183,102 +625,169 @@ impl<'a> View<'a> {
       return CaptureEvent::Exit;
     }
 
+    // A single reader of keys, not to lose the bytes it read ahead
+    let mut keys = stdin.keys();
     let mut typed_hint: String = "".to_owned();
-    let longest_hint = self
-      .matches
-      .iter()
-      .filter_map(|m| m.hint.clone())
-      .max_by(|x, y| x.len().cmp(&y.len()))
-      .unwrap()
-      .clone();
+    let mut leader_action: Option<&str> = None;
+    // The first g of gg while navigating
+    let mut pending_g = false;
 
     self.render(stdout, &typed_hint);
 
     loop {
-      match stdin.keys().next() {
+      match keys.next() {
         Some(key) => {
           match key {
             Ok(key) => {
-              match key {
-                Key::Esc => {
-                  if self.multi && !typed_hint.is_empty() {
+              let pressed = key;
+
+              // Letters typed with a modifier pick their hint along with its action
+              let (key, key_action) = match key {
+                Key::Alt(ch) if self.action(Modifier::Alt).is_some() => (Key::Char(ch), self.action(Modifier::Alt)),
+                Key::Ctrl(ch) if self.action(Modifier::Ctrl).is_some() => (Key::Char(ch), self.action(Modifier::Ctrl)),
+                Key::Char(ch) if ch.is_uppercase() && self.action(Modifier::Shift).is_some() => (
+                  Key::Char(ch.to_lowercase().next().unwrap_or(ch)),
+                  self.action(Modifier::Shift),
+                ),
+                key => (key, None),
+              };
+
+              if let Some(prompt) = self.prompt {
+                self.prompt_key(prompt, pressed);
+              } else if self.navigating && self.navigate(pressed, &mut pending_g) {
+                // The selection moved, or the navigation is over
+              } else {
+                match key {
+                  Key::Esc => {
+                    if leader_action.is_some() {
+                      leader_action = None;
+                      typed_hint.clear();
+                    } else if self.multi && !typed_hint.is_empty() {
+                      typed_hint.clear();
+                    } else if !self.filter.is_empty() || self.pattern.is_some() {
+                      typed_hint.clear();
+                      self.filter.clear();
+                      self.pattern = None;
+                      self.apply_filter();
+                    } else if !self.regexp.is_empty() {
+                      typed_hint.clear();
+                      self.regexp.clear();
+                      self.apply_regexp();
+                    } else {
+                      break;
+                    }
+                  }
+                  Key::Char('\t') => {
                     typed_hint.clear();
-                  } else {
-                    break;
+                    self.cycle_pattern(true);
                   }
-                }
-                Key::Up => {
-                  self.prev();
-                }
-                Key::Down => {
-                  self.next();
-                }
-                Key::Left => {
-                  self.prev();
-                }
-                Key::Right => {
-                  self.next();
-                }
-                Key::Backspace => {
-                  typed_hint.pop();
-                }
-                Key::Char(ch) => {
-                  match ch {
-                    '\n' => match self.matches.iter().enumerate().find(|&h| h.0 == self.skip) {
-                      Some(hm) => {
-                        self.chosen.push((hm.1.text.to_string(), false));
+                  Key::BackTab => {
+                    typed_hint.clear();
+                    self.cycle_pattern(false);
+                  }
+                  Key::Up => {
+                    self.select_line(false);
+                  }
+                  Key::Down => {
+                    self.select_line(true);
+                  }
+                  Key::Left => {
+                    self.prev();
+                  }
+                  Key::Right => {
+                    self.next();
+                  }
+                  Key::Backspace => {
+                    typed_hint.pop();
+                  }
+                  Key::Char(ch) => {
+                    match ch {
+                      '\n' => {
+                        // A filter may leave nothing to pick
+                        if let Some(mat) = self.matches.get(self.skip) {
+                          self
+                            .chosen
+                            .push((mat.copy.clone(), mat.pattern.to_string(), leader_action));
 
-                        if !self.multi {
+                          if !self.multi {
+                            return CaptureEvent::Hint;
+                          }
+                        }
+                      }
+                      ' ' => {
+                        if self.multi {
+                          // Finalize the multi selection
                           return CaptureEvent::Hint;
+                        } else {
+                          // Enable the multi selection
+                          self.multi = true;
                         }
                       }
-                      _ => panic!("Match not found?"),
-                    },
-                    ' ' => {
-                      if self.multi {
-                        // Finalize the multi selection
-                        return CaptureEvent::Hint;
-                      } else {
-                        // Enable the multi selection
-                        self.multi = true;
+                      key if typed_hint.is_empty() && self.action(Modifier::Leader(key)).is_some() => {
+                        leader_action = self.action(Modifier::Leader(key));
                       }
-                    }
-                    key => {
-                      let key = key.to_string();
-                      let lower_key = key.to_lowercase();
-
-                      typed_hint.push_str(lower_key.as_str());
+                      key if typed_hint.is_empty() && Some(key) == self.navigation_key => {
+                        self.navigating = true;
+                        leader_action = None;
+                      }
+                      '/' if typed_hint.is_empty() && !self.state.is_hint_key('/') => {
+                        self.prompt = Some(Prompt::Filter);
+                        leader_action = None;
+                      }
+                      '?' if typed_hint.is_empty() && !self.state.is_hint_key('?') => {
+                        self.show_status = !self.show_status;
+                      }
+                      ':' if typed_hint.is_empty() && !self.state.is_hint_key(':') => {
+                        self.prompt = Some(Prompt::Regexp);
+                        leader_action = None;
+                      }
+                      key => {
+                        typed_hint.push(key);
 
-                      let selection = self.matches.iter().find(|mat| mat.hint == Some(typed_hint.clone()));
+                        let selection = self.matches.iter().find(|mat| mat.hint == Some(typed_hint.clone()));
 
-                      match selection {
-                        Some(mat) => {
-                          self.chosen.push((mat.text.to_string(), key != lower_key));
+                        match selection {
+                          Some(mat) => {
+                            self
+                              .chosen
+                              .push((mat.copy.clone(), mat.pattern.to_string(), key_action.or(leader_action)));
 
-                          if self.multi {
-                            typed_hint.clear();
-                          } else {
-                            return CaptureEvent::Hint;
+                            if self.multi {
+                              typed_hint.clear();
+                              leader_action = None;
+                            } else {
+                              return CaptureEvent::Hint;
+                            }
                           }
-                        }
-                        None => {
-                          if !self.multi && typed_hint.len() >= longest_hint.len() {
-                            break;
+                          None => {
+                            let longest_hint = self
+                              .matches
+                              .iter()
+                              .filter_map(|mat| mat.hint.as_ref().map(|hint| hint.len()))
+                              .max()
+                              .unwrap_or(0);
+
+                            if !self.multi && typed_hint.len() >= longest_hint {
+                              break;
+                            }
                           }
                         }
                       }
                     }
                   }
-                }
-                _ => {
-                  // Unknown key
+                  _ => {
+                    // Unknown key
+                  }
                 }
               }
             }
             Err(err) => panic!("{}", err),
           }
 
-          stdin.keys().for_each(|_| { /* Skip the rest of stdin buffer */ })
+          // Text pasted or typed fast in a prompt is kept
+          if self.prompt.is_none() {
+            keys.by_ref().for_each(|_| { /* Skip the rest of stdin buffer */ })
+          }
         }
         _ => {
           // Nothing in the buffer. Wait for a bit...
@@ -293,9 +802,9 @@ impl<'a> View<'a> {
     CaptureEvent::Exit
   }
 
-  pub fn present(&mut self) -> Vec<(String, bool)> {
+  pub fn present(&mut self) -> Result<Vec<Chosen<'a>>, error::Error> {
     let mut stdin = async_stdin();
-    let mut stdout = AlternateScreen::from(stdout().into_raw_mode().unwrap());
+    let mut stdout = AlternateScreen::from(stdout().into_raw_mode().map_err(error::Error::Terminal)?);
 
     let hints = match self.listen(&mut stdin, &mut stdout) {
       CaptureEvent::Exit => vec![],
@@ -304,7 +813,7 @@ impl<'a> View<'a> {
 
     write!(stdout, "{}", cursor::Show).unwrap();
 
-    hints
+    Ok(hints)
   }
 }
 
@@ -312,32 +821,19 @@ impl<'a> View<'a> {
 mod tests {
   use super::*;
 
-  fn split(output: &str) -> Vec<&str> {
-    output.split("\n").collect::<Vec<&str>>()
+  fn split(output: &str) -> Vec<ansi::Line<'_>> {
+    output.split("\n").map(ansi::Line::parse).collect::<Vec<ansi::Line>>()
+  }
+
+  fn view<'a>(state: &'a mut state::State<'a>) -> View<'a> {
+    View::new(state, Options::default())
   }
 
   #[test]
   fn hint_text() {
     let lines = split("lorem 127.0.0.1 lorem");
-    let custom = [].to_vec();
-    let mut state = state::State::new(&lines, "abcd", &custom);
-    let mut view = View {
-      state: &mut state,
-      skip: 0,
-      multi: false,
-      contrast: false,
-      position: &"",
-      matches: vec![],
-      select_foreground_color: colors::get_color("default"),
-      select_background_color: colors::get_color("default"),
-      multi_foreground_color: colors::get_color("default"),
-      multi_background_color: colors::get_color("default"),
-      foreground_color: colors::get_color("default"),
-      background_color: colors::get_color("default"),
-      hint_background_color: colors::get_color("default"),
-      hint_foreground_color: colors::get_color("default"),
-      chosen: vec![],
-    };
+    let mut state = state::State::new(&lines, alphabets::Alphabet::new("abcd"), state::Options::default()).unwrap();
+    let mut view = view(&mut state);
 
     let result = view.make_hint_text("a");
     assert_eq!(result, "a".to_string());
@@ -346,4 +842,206 @@ mod tests {
     let result = view.make_hint_text("a");
     assert_eq!(result, "[a]".to_string());
   }
+
+  #[test]
+  fn hint_position() {
+    let lines = split("See https://foo.bar/\nx lorem");
+    let mut state = state::State::new(
+      &lines,
+      alphabets::Alphabet::new("abcd"),
+      state::Options {
+        wrapped: &["url"],
+        width: Some(20),
+        ..Default::default()
+      },
+    )
+    .unwrap();
+    let mut view = view(&mut state);
+    view.position = "right";
+
+    let tail = view.matches.first().unwrap().spans.last().unwrap().text;
+    assert_eq!(tail, "x");
+    assert_eq!(view.hint_position(tail, "ab"), 0);
+    assert_eq!(view.hint_position("https://foo.bar/", "ab"), 14);
+
+    view.position = "off_left";
+    assert_eq!(view.hint_position(tail, "ab"), -2);
+  }
+
+  #[test]
+  fn reverse_without_matches() {
+    let lines = split("lorem ipsum");
+    let mut state = state::State::new(&lines, alphabets::Alphabet::new("abcd"), state::Options::default()).unwrap();
+    let view = View::new(
+      &mut state,
+      Options {
+        reverse: true,
+        ..Default::default()
+      },
+    );
+
+    assert!(view.matches.is_empty());
+    assert_eq!(view.skip, 0);
+  }
+
+  #[test]
+  fn filter_matches() {
+    let lines = split("lorem 127.0.0.1 lorem 10.0.0.1 /var/log/syslog");
+    let mut state = state::State::new(&lines, alphabets::Alphabet::new("abcd"), state::Options::default()).unwrap();
+    let mut view = view(&mut state);
+    let hints = view.matches.iter().map(|mat| mat.hint.clone()).collect::<Vec<_>>();
+
+    view.filter = "10.0".to_string();
+    view.apply_filter();
+
+    assert_eq!(view.matches.len(), 1);
+    assert_eq!(view.matches.first().unwrap().text, "10.0.0.1");
+    assert_eq!(view.matches.first().unwrap().hint.clone().unwrap(), "a");
+
+    view.filter = "wat".to_string();
+    view.apply_filter();
+
+    assert!(view.matches.is_empty());
+
+    view.filter.clear();
+    view.apply_filter();
+
+    assert_eq!(
+      view.matches.iter().map(|mat| mat.hint.clone()).collect::<Vec<_>>(),
+      hints
+    );
+  }
+
+  #[test]
+  fn prompt_ignores_tab() {
+    let lines = split("lorem 127.0.0.1 lorem 10.0.0.1");
+    let mut state = state::State::new(&lines, alphabets::Alphabet::new("abcd"), state::Options::default()).unwrap();
+    let mut view = view(&mut state);
+
+    view.prompt = Some(Prompt::Filter);
+
+    for key in [Key::Char('1'), Key::Char('\t'), Key::Char('0'), Key::Char('\u{7}')] {
+      view.prompt_key(Prompt::Filter, key);
+    }
+
+    assert_eq!(view.filter, "10");
+    assert_eq!(view.matches.len(), 1);
+    assert_eq!(view.matches.first().unwrap().text, "10.0.0.1");
+  }
+
+  #[test]
+  fn search_regexp() {
+    let lines = split("JIRA-12 and JIRA-345 at 127.0.0.1");
+    let mut state = state::State::new(&lines, alphabets::Alphabet::new("abcd"), state::Options::default()).unwrap();
+    let mut view = view(&mut state);
+
+    view.regexp = r"JIRA-\d+".to_string();
+    assert!(view.apply_regexp());
+    assert_eq!(view.matches.len(), 2);
+
+    view.filter = "345".to_string();
+    view.apply_filter();
+
+    assert_eq!(view.matches.len(), 1);
+    assert_eq!(view.matches.first().unwrap().text, "JIRA-345");
+
+    view.regexp = "(JIRA".to_string();
+    assert!(!view.apply_regexp());
+
+    view.regexp.clear();
+    assert!(view.apply_regexp());
+    assert_eq!(view.matches.first().unwrap().text, "127.0.0.1");
+  }
+
+  #[test]
+  fn cycle_patterns() {
+    let lines = split("lorem 127.0.0.1 /var/log/syslog 10.0.0.1");
+    let mut state = state::State::new(&lines, alphabets::Alphabet::new("abcd"), state::Options::default()).unwrap();
+    let mut view = view(&mut state);
+    let hints = view.matches.iter().map(|mat| mat.hint.clone()).collect::<Vec<_>>();
+
+    view.cycle_pattern(true);
+    assert_eq!(view.pattern, Some("ip"));
+    assert_eq!(view.matches.len(), 2);
+
+    view.cycle_pattern(true);
+    assert_eq!(view.pattern, Some("path"));
+    assert_eq!(view.matches.len(), 1);
+    assert_eq!(view.matches.first().unwrap().hint.clone().unwrap(), "a");
+
+    view.cycle_pattern(true);
+    assert_eq!(view.pattern, None);
+    assert_eq!(
+      view.matches.iter().map(|mat| mat.hint.clone()).collect::<Vec<_>>(),
+      hints
+    );
+
+    view.cycle_pattern(false);
+    assert_eq!(view.pattern, Some("path"));
+
+    view.pattern = None;
+    view.filter = "log".to_string();
+    view.apply_filter();
+
+    view.cycle_pattern(true);
+    assert_eq!(view.pattern, Some("path"));
+    assert_eq!(view.matches.len(), 1);
+
+    view.cycle_pattern(true);
+    assert_eq!(view.pattern, None);
+    assert_eq!(view.matches.len(), 1);
+  }
+
+  #[test]
+  fn status_line() {
+    let lines = split("lorem 127.0.0.1 /var/log/syslog 10.0.0.1");
+    let mut state = state::State::new(&lines, alphabets::Alphabet::new("abcd/"), state::Options::default()).unwrap();
+    let mut view = view(&mut state);
+
+    assert_eq!(
+      view.status_line(""),
+      "single | : search  Tab pattern  Space multi  Enter pick  Esc back  ? help"
+    );
+
+    view.multi = true;
+    view.filter = "10".to_string();
+    view.cycle_pattern(true);
+
+    assert_eq!(
+      view.status_line("b"),
+      "multi | typed: b | chosen: 0 | pattern: ip | filter: 10 | : search  Tab pattern  Space done  Enter pick  Esc back  ? help"
+    );
+  }
+
+  #[test]
+  fn navigate_matches() {
+    let lines = split("foo 10.0.0.1 bar 10.0.0.2\nlorem\n   10.0.0.3     10.0.0.4");
+    let mut state = state::State::new(&lines, alphabets::Alphabet::new("abcd"), state::Options::default()).unwrap();
+    let mut view = view(&mut state);
+    let mut pending = false;
+
+    view.navigating = true;
+
+    let mut press = |view: &mut View, ch: char| {
+      assert!(view.navigate(Key::Char(ch), &mut pending));
+      view.skip
+    };
+
+    assert_eq!(press(&mut view, 'j'), 2);
+    assert_eq!(press(&mut view, 'l'), 3);
+    assert_eq!(press(&mut view, 'l'), 3);
+    assert_eq!(press(&mut view, 'k'), 1);
+    assert_eq!(press(&mut view, 'h'), 0);
+    assert_eq!(press(&mut view, 'h'), 0);
+    assert_eq!(press(&mut view, 'G'), 3);
+    assert_eq!(press(&mut view, 'g'), 3);
+    assert_eq!(press(&mut view, 'g'), 0);
+    assert_eq!(press(&mut view, 'w'), 1);
+    assert_eq!(press(&mut view, 'b'), 0);
+    assert_eq!(press(&mut view, 'x'), 0);
+
+    assert!(!view.navigate(Key::Char('\n'), &mut pending));
+    assert!(view.navigate(Key::Esc, &mut pending));
+    assert!(!view.navigating);
+  }
 }
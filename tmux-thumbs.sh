@@ -48,6 +48,26 @@ function add-param() {
 add-param command        string
 add-param upcase-command string
 add-param multi-command  string
+add-param patterns-file  string
 add-param osc52          boolean
 
+function add-pattern-commands() {
+  local opt value
+  for opt in $(tmux show -g 2> /dev/null | grep -o '^@thumbs-command-[[:alnum:]_-]*'); do
+    value="$(tmux show -vg "${opt}" 2> /dev/null)" || continue
+    PARAMS+=("--pattern-command=${opt#@thumbs-command-}=${value}")
+  done
+}
+
+function add-action-commands() {
+  local opt value
+  for opt in $(tmux show -g 2> /dev/null | grep -o '^@thumbs-action-command-[[:alnum:]_]*'); do
+    value="$(tmux show -vg "${opt}" 2> /dev/null)" || continue
+    PARAMS+=("--action-command=${opt#@thumbs-action-command-}=${value}")
+  done
+}
+
+add-pattern-commands
+add-action-commands
+
 "${TMUX_THUMBS_BINARY}" "${PARAMS[@]}" || true